@@ -8,16 +8,12 @@ if [[ "$status" != "0" ]]; then
     exit $status
 fi
 
-# Kill current running bar
-killall ba
-killall lemonbar
-
 mkdir -p ~/.local/bin
 
 # Copy over the bar
-cp target/release/ba ~/.local/bin/ba
+# The running bar keeps using the old binary until it is replaced
+cp --remove-destination target/release/ba ~/.local/bin/ba
 cp togglebar ~/.local/bin/togglebar
 
-# Start the bar
-./togglebar &
-disown %./togglebar
+# Start the bar, replacing the one already running
+setsid ~/.local/bin/ba run --replace > /dev/null 2>&1 < /dev/null &
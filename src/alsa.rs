@@ -0,0 +1,238 @@
+//! Just enough of the ALSA control interface to read, change and watch
+//! a mixer control.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::os::raw::c_long;
+use std::os::unix::io::AsRawFd;
+
+// Requests, see sound/asound.h
+const IOCTL_ELEM_INFO: libc::Ioctl = read_write(0x11, size_of::<ElemInfo>());
+const IOCTL_ELEM_READ: libc::Ioctl = read_write(0x12, size_of::<ElemValue>());
+const IOCTL_ELEM_WRITE: libc::Ioctl = read_write(0x13, size_of::<ElemValue>());
+const IOCTL_SUBSCRIBE_EVENTS: libc::Ioctl = read_write(0x16, size_of::<libc::c_int>());
+
+const IFACE_MIXER: i32 = 2;
+const EVENT_ELEM: i32 = 0;
+const EVENT_MASK_VALUE: u32 = 1;
+
+const NAME_MAX: usize = 44;
+const VALUES_MAX: usize = 128;
+
+/// struct snd_ctl_elem_id
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElemId {
+    numid: u32,
+    iface: i32,
+    device: u32,
+    subdevice: u32,
+    name: [u8; NAME_MAX],
+    index: u32,
+}
+
+/// struct snd_ctl_elem_info, with the value of integer elements
+#[repr(C)]
+struct ElemInfo {
+    id: ElemId,
+    kind: i32,
+    access: u32,
+    count: u32,
+    owner: libc::pid_t,
+    value: InfoValue,
+    reserved: [u8; 64],
+}
+
+#[repr(C)]
+union InfoValue {
+    /// The minimum, maximum and step
+    integer: [c_long; 3],
+    integer64: [i64; 3],
+    reserved: [u8; 128],
+}
+
+/// struct snd_ctl_elem_value, with the values of integer elements
+#[repr(C)]
+struct ElemValue {
+    id: ElemId,
+    indirect: u32,
+    value: ValueValue,
+    reserved: [u8; 128],
+}
+
+#[repr(C)]
+union ValueValue {
+    integer: [c_long; VALUES_MAX],
+    integer64: [i64; 64],
+    bytes: [u8; 512],
+}
+
+/// struct snd_ctl_event
+#[repr(C)]
+struct Event {
+    kind: i32,
+    data: EventData,
+}
+
+#[repr(C)]
+union EventData {
+    elem: EventElem,
+    data8: [u8; 60],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EventElem {
+    mask: u32,
+    id: ElemId,
+}
+
+// The layout on 64-bit Linux, as printed by sizeof in C
+#[cfg(target_pointer_width = "64")]
+const _: () = {
+    assert!(size_of::<ElemId>() == 64);
+    assert!(size_of::<ElemInfo>() == 272);
+    assert!(size_of::<ElemValue>() == 1224);
+    assert!(size_of::<Event>() == 72);
+    assert!(std::mem::offset_of!(ElemInfo, count) == 72);
+    assert!(std::mem::offset_of!(ElemInfo, value) == 80);
+    assert!(std::mem::offset_of!(ElemValue, value) == 72);
+};
+
+#[cfg(target_arch = "x86_64")]
+const _: () = {
+    assert!(IOCTL_ELEM_INFO == 0xc110_5511);
+    assert!(IOCTL_ELEM_READ == 0xc4c8_5512);
+    assert!(IOCTL_ELEM_WRITE == 0xc4c8_5513);
+    assert!(IOCTL_SUBSCRIBE_EVENTS == 0xc004_5516);
+};
+
+/// The control device of a sound card.
+pub struct Control {
+    file: File,
+}
+
+impl Control {
+    /// Open the control device of card number `card`.
+    pub fn open(card: u32) -> io::Result<Control> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("/dev/snd/controlC{}", card))?;
+
+        Ok(Control { file })
+    }
+
+    /// Get the smallest and largest value of an element, such as
+    /// `Master Playback Volume`, and how many channels it has.
+    pub fn range(&self, name: &str) -> io::Result<(i64, i64, usize)> {
+        let mut info: ElemInfo = unsafe { std::mem::zeroed() };
+        info.id = element(name)?;
+        self.ioctl(IOCTL_ELEM_INFO, &mut info)?;
+
+        let [min, max, _] = unsafe { info.value.integer };
+        Ok((long(min), long(max), info.count as usize))
+    }
+
+    /// Read the value of every channel of an element.
+    pub fn read(&self, name: &str) -> io::Result<Vec<i64>> {
+        let (_, _, channels) = self.range(name)?;
+
+        let mut value: ElemValue = unsafe { std::mem::zeroed() };
+        value.id = element(name)?;
+        self.ioctl(IOCTL_ELEM_READ, &mut value)?;
+
+        let values = unsafe { value.value.integer };
+        Ok(values.iter().take(channels).map(|&v| long(v)).collect())
+    }
+
+    /// Set the value of every channel of an element.
+    pub fn write(&self, name: &str, values: &[i64]) -> io::Result<()> {
+        let mut value: ElemValue = unsafe { std::mem::zeroed() };
+        value.id = element(name)?;
+        for (channel, &v) in unsafe { value.value.integer.iter_mut() }.zip(values) {
+            *channel = v as c_long;
+        }
+
+        self.ioctl(IOCTL_ELEM_WRITE, &mut value)
+    }
+
+    /// Ask to be told about changes, see `wait`.
+    pub fn subscribe(&self) -> io::Result<()> {
+        let mut subscribe: libc::c_int = 1;
+        self.ioctl(IOCTL_SUBSCRIBE_EVENTS, &mut subscribe)
+    }
+
+    /// Wait until the value of an element changes.
+    pub fn wait(&mut self) -> io::Result<()> {
+        let mut events = [0u8; size_of::<Event>() * 16];
+
+        loop {
+            let read = self.file.read(&mut events)?;
+            if read == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+
+            let changed = events[..read].chunks_exact(size_of::<Event>()).any(|event| {
+                let event = unsafe { std::ptr::read_unaligned(event.as_ptr() as *const Event) };
+                event.kind == EVENT_ELEM && unsafe { event.data.elem.mask } & EVENT_MASK_VALUE != 0
+            });
+            if changed {
+                return Ok(());
+            }
+        }
+    }
+
+    fn ioctl<T>(&self, request: libc::Ioctl, data: &mut T) -> io::Result<()> {
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request, data as *mut T) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+/// Create the id of a mixer element, which the kernel looks up by its name.
+fn element(name: &str) -> io::Result<ElemId> {
+    if name.len() >= NAME_MAX {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "element name is too long"));
+    }
+
+    let mut id: ElemId = unsafe { std::mem::zeroed() };
+    id.iface = IFACE_MIXER;
+    id.name[..name.len()].copy_from_slice(name.as_bytes());
+
+    Ok(id)
+}
+
+#[allow(clippy::useless_conversion)] // A long is 32 bits on 32-bit targets
+fn long(value: c_long) -> i64 {
+    i64::from(value)
+}
+
+/// Encode an `_IOWR('U', nr, size)` request, see asm-generic/ioctl.h and
+/// the architectures that use other bits for the direction.
+const fn read_write(nr: u32, size: usize) -> libc::Ioctl {
+    #[cfg(any(
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "sparc",
+        target_arch = "sparc64",
+    ))]
+    let (dir, dir_shift, size_mask) = (2 | 4, 29, (1 << 13) - 1);
+    #[cfg(not(any(
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "sparc",
+        target_arch = "sparc64",
+    )))]
+    let (dir, dir_shift, size_mask) = (2 | 1, 30, (1 << 14) - 1);
+
+    let request: u32 = dir << dir_shift | (size as u32 & size_mask) << 16 | (b'U' as u32) << 8 | nr;
+    request as libc::Ioctl
+}
@@ -0,0 +1,628 @@
+//! Just enough iCalendar to list the events of a day, including the ones
+//! repeating by an `RRULE`.
+
+use std::convert::TryFrom;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+
+use crate::{local_zone, sorted_entries, zone_offset};
+
+/// Where the times of an event are, from the `TZID` of its start.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EventZone {
+    /// In local time, wherever that is
+    #[default]
+    Floating,
+    Utc,
+    /// An IANA time zone, such as `Europe/Stockholm`
+    Named(String),
+}
+
+/// How often an event repeats, from its `RRULE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The rule an event repeats by, from its `RRULE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDateTime>,
+    /// Weekdays, with an ordinal such as -1 for the last one in the month,
+    /// or 0 for all of them
+    pub by_day: Vec<(i32, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+/// An event from an iCalendar file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    /// The start in the time zone of the event
+    pub start: NaiveDateTime,
+    pub all_day: bool,
+    pub zone: EventZone,
+    pub duration: Duration,
+    pub rule: Option<RecurrenceRule>,
+    /// Starts of occurrences that have been removed
+    pub exceptions: Vec<NaiveDateTime>,
+    /// Set if this event replaces one occurrence of a repeating event
+    pub recurrence_id: Option<NaiveDateTime>,
+}
+
+/// An occurrence of an event, in local time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Occurrence {
+    pub start: NaiveDateTime,
+    pub all_day: bool,
+    pub summary: String,
+}
+
+/// Read the events of an `.ics` file, or of every `.ics` file in a directory
+/// and its subdirectories.
+pub fn read_calendars(path: &Path) -> Vec<Event> {
+    if path.is_dir() {
+        return sorted_entries(path).iter().flat_map(|entry| read_calendars(entry)).collect();
+    }
+
+    if path.extension().is_none_or(|extension| extension != "ics") {
+        return Vec::new();
+    }
+
+    match read_to_string(path) {
+        Ok(ics) => parse_ics(&ics),
+        Err(e) => {
+            warn!("Failed to read calendar {}: {}", path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// Parse the events of an iCalendar file.
+///
+/// # Examples
+///
+/// ```
+/// use ba::ical::{parse_ics, EventZone, Frequency};
+/// use chrono::{Duration, NaiveDate, Weekday};
+///
+/// let events = parse_ics("\
+/// BEGIN:VCALENDAR\r
+/// BEGIN:VEVENT\r
+/// UID:1\r
+/// DTSTART;TZID=Europe/Stockholm:20261019T091500\r
+/// DURATION:PT15M\r
+/// RRULE:FREQ=WEEKLY;BYDAY=MO,-1FR;COUNT=10\r
+/// SUMMARY:Standup\\, with\r
+///   a folded line\r
+/// END:VEVENT\r
+/// END:VCALENDAR\r
+/// ");
+///
+/// let event = &events[0];
+/// assert_eq!(event.summary, "Standup, with a folded line");
+/// assert_eq!(event.start, NaiveDate::from_ymd_opt(2026, 10, 19).unwrap().and_hms_opt(9, 15, 0).unwrap());
+/// assert_eq!(event.zone, EventZone::Named(String::from("Europe/Stockholm")));
+/// assert_eq!(event.duration, Duration::minutes(15));
+///
+/// let rule = event.rule.as_ref().unwrap();
+/// assert_eq!(rule.frequency, Frequency::Weekly);
+/// assert_eq!(rule.count, Some(10));
+/// assert_eq!(rule.by_day, vec![(0, Weekday::Mon), (-1, Weekday::Fri)]);
+/// ```
+pub fn parse_ics(ics: &str) -> Vec<Event> {
+    // Long lines are folded by starting the next line with a space or tab
+    let unfolded = ics.replace("\r\n", "\n").replace("\n ", "").replace("\n\t", "");
+
+    let mut events = Vec::new();
+    let mut event: Option<Event> = None;
+    let mut end = None;
+    let mut has_start = false;
+    let mut nested = 0;
+
+    for line in unfolded.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some(split) => split,
+            None => continue,
+        };
+        let mut params = name.split(';');
+        let name = params.next().unwrap_or_default().to_uppercase();
+        let tzid = params
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.eq_ignore_ascii_case("TZID"))
+            .map(|(_, zone)| zone.trim_matches('"'));
+
+        let current = match (name.as_str(), event.as_mut()) {
+            ("BEGIN", None) if value == "VEVENT" => {
+                event = Some(Event::default());
+                end = None;
+                has_start = false;
+                continue;
+            }
+            ("END", Some(current)) if value == "VEVENT" && nested == 0 => {
+                if let (Some(end), false) = (end, current.duration > Duration::zero()) {
+                    current.duration = end - current.start;
+                }
+                if current.all_day && current.duration <= Duration::zero() {
+                    current.duration = Duration::days(1);
+                }
+                if has_start {
+                    events.push(current.clone());
+                }
+                event = None;
+                continue;
+            }
+            // Alarms and other components inside the event
+            ("BEGIN", Some(_)) => {
+                nested += 1;
+                continue;
+            }
+            ("END", Some(_)) => {
+                nested -= 1;
+                continue;
+            }
+            (_, Some(current)) if nested == 0 => current,
+            _ => continue,
+        };
+
+        match name.as_str() {
+            "UID" => current.uid = String::from(value),
+            "SUMMARY" => current.summary = unescape_ics(value),
+            "DTSTART" => {
+                if let Some((start, all_day, zone)) = parse_ics_time(value, tzid) {
+                    current.start = start;
+                    current.all_day = all_day;
+                    current.zone = zone;
+                    has_start = true;
+                }
+            }
+            "DTEND" => end = parse_ics_time(value, tzid).map(|(end, _, _)| end),
+            "DURATION" => current.duration = parse_ics_duration(value).unwrap_or_default(),
+            "RRULE" => current.rule = parse_rrule(value),
+            "EXDATE" => current.exceptions.extend(
+                value.split(',').filter_map(|date| Some(parse_ics_time(date, tzid)?.0))
+            ),
+            "RECURRENCE-ID" => current.recurrence_id = parse_ics_time(value, tzid).map(|(id, _, _)| id),
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Get the occurrences of events on a day in local time, all-day events
+/// first and the rest by their start. Time zones are read from `<root>`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::ical::{events_on, read_calendars};
+/// use chrono::NaiveDate;
+/// use std::path::Path;
+///
+/// let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+/// let events = read_calendars(&fixtures.join("calendar"));
+/// let root = fixtures.join("zoneinfo");
+///
+/// // Local time is Swedish, which is the time zone of events without one
+/// std::env::set_var("TZ", "Europe/Stockholm");
+/// let agenda = |month, day| -> Vec<String> {
+///     let date = NaiveDate::from_ymd_opt(2026, month, day).unwrap();
+///     events_on(root.to_str().unwrap(), &events, date)
+///         .iter()
+///         .map(|o| if o.all_day {
+///             o.summary.clone()
+///         } else {
+///             format!("{} {}", o.start.format("%H:%M"), o.summary)
+///         })
+///         .collect()
+/// };
+///
+/// // A moved occurrence, a yearly and a multi-day all-day event
+/// assert_eq!(agenda(9, 16), vec!["Trip to Gothenburg", "Birthday", "07:00 Gym", "10:00 Standup (moved)"]);
+/// // An excluded occurrence
+/// assert_eq!(agenda(9, 14), Vec::<String>::new());
+/// // The last Friday of the month
+/// assert_eq!(agenda(9, 25), vec!["07:00 Gym", "09:15 Standup", "14:00 Monthly review, all teams"]);
+/// assert_eq!(agenda(11, 27), vec!["09:15 Standup", "14:00 Monthly review, all teams"]);
+/// // Counted out
+/// assert_eq!(agenda(12, 25), vec!["09:15 Standup"]);
+/// // Every other week until a date
+/// assert_eq!(agenda(10, 22), vec!["13:00 Sprint planning with a long title that is folded over two lines"]);
+/// assert_eq!(agenda(11, 5), Vec::<String>::new());
+/// // Only in September
+/// assert_eq!(agenda(10, 1), Vec::<String>::new());
+/// // At 17:00 in Tokyo, until Sweden leaves summer time, except on October 13
+/// assert_eq!(agenda(10, 6), vec!["10:00 Call with Tokyo"]);
+/// assert_eq!(agenda(10, 13), Vec::<String>::new());
+/// assert_eq!(agenda(10, 27), vec!["09:00 Call with Tokyo"]);
+/// // At 22:00 UTC the day before
+/// assert_eq!(agenda(10, 9), vec!["00:00 Deploy", "09:15 Standup"]);
+/// ```
+pub fn events_on(root: &str, events: &[Event], day: NaiveDate) -> Vec<Occurrence> {
+    let day_start = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let day_end = day_start + Duration::days(1);
+
+    // Occurrences that have been replaced by another event
+    let replaced: Vec<(&str, NaiveDateTime)> = events
+        .iter()
+        .filter_map(|event| Some((event.uid.as_str(), event.recurrence_id?)))
+        .collect();
+
+    let mut occurrences: Vec<Occurrence> = events
+        .iter()
+        .flat_map(|event| {
+            // Leave a day for the time zone of the event being off from local time
+            let starts = match event.recurrence_id {
+                Some(_) => vec![event.start],
+                None => event.recurrences(day_start - Duration::days(1) - event.duration,
+                    day_end + Duration::days(1)),
+            };
+
+            starts
+                .into_iter()
+                .filter(|start| !event.exceptions.contains(start))
+                .filter(|start| event.recurrence_id.is_some()
+                    || !replaced.contains(&(event.uid.as_str(), *start)))
+                .map(|start| local_time(root, start, &event.zone))
+                .filter(|start| {
+                    let end = *start + event.duration;
+                    *start < day_end && (end > day_start || *start >= day_start)
+                })
+                .map(|start| Occurrence { start, all_day: event.all_day, summary: event.summary.clone() })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    occurrences.sort_by_key(|occurrence| (!occurrence.all_day, occurrence.start));
+    occurrences
+}
+
+impl Event {
+    /// Get the starts of the occurrences from about `start` up to `end`, in
+    /// the time zone of the event. Removed occurrences are included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::ical::parse_ics;
+    /// use chrono::NaiveDate;
+    ///
+    /// let events = parse_ics("\
+    /// BEGIN:VEVENT
+    /// DTSTART:19900101T080000
+    /// RRULE:FREQ=DAILY;INTERVAL=3
+    /// SUMMARY:Water the plants
+    /// END:VEVENT
+    /// ");
+    /// let at = |day| NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(8, 0, 0).unwrap();
+    ///
+    /// assert_eq!(events[0].recurrences(at(19), at(24)), vec![at(19), at(22)]);
+    /// ```
+    pub fn recurrences(&self, start: NaiveDateTime, end: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let rule = match &self.rule {
+            Some(rule) => rule,
+            None => return vec![self.start],
+        };
+
+        // Occurrences are counted from the first one, so with a count
+        // none of the periods can be skipped
+        let mut period = match rule.count {
+            Some(_) => 0,
+            None => rule.periods_before(self.start.date(), start.date()),
+        };
+
+        let mut starts = Vec::new();
+        let mut count = 0;
+
+        loop {
+            let (period_start, dates) = rule.period(self.start.date(), period);
+            if period_start.and_hms_opt(0, 0, 0).is_none_or(|start| start > end) {
+                break;
+            }
+
+            for start in dates.into_iter().map(|date| date.and_time(self.start.time())) {
+                if start < self.start {
+                    continue;
+                }
+                if start > end
+                    || rule.until.is_some_and(|until| start > until)
+                    || rule.count.is_some_and(|limit| count >= limit)
+                {
+                    return starts;
+                }
+
+                count += 1;
+                starts.push(start);
+            }
+
+            period = match period.checked_add(rule.interval.max(1)) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        starts
+    }
+}
+
+impl RecurrenceRule {
+    /// Get the number of the last period the event repeats in that starts at
+    /// or before the period containing `date`, counted from the one
+    /// containing `start`.
+    fn periods_before(&self, start: NaiveDate, date: NaiveDate) -> u32 {
+        let monday = |date: NaiveDate| date - Duration::days(i64::from(date.weekday().num_days_from_monday()));
+        let months = |date: NaiveDate| i64::from(date.year()) * 12 + i64::from(date.month0());
+
+        let periods = match self.frequency {
+            Frequency::Daily => (date - start).num_days(),
+            Frequency::Weekly => (monday(date) - monday(start)).num_weeks(),
+            Frequency::Monthly => months(date) - months(start),
+            Frequency::Yearly => i64::from(date.year() - start.year()),
+        };
+
+        let interval = i64::from(self.interval.max(1));
+        u32::try_from(periods.max(0) / interval * interval).unwrap_or(u32::MAX)
+    }
+
+    /// Get the start of the period `n` periods after the one containing `start`,
+    /// and the dates in it matching the rule.
+    fn period(&self, start: NaiveDate, n: u32) -> (NaiveDate, Vec<NaiveDate>) {
+        let add_months = |months: u32| {
+            let month = start.month0() + months;
+            NaiveDate::from_ymd_opt(start.year() + (month / 12) as i32, month % 12 + 1, 1)
+        };
+
+        let (period_start, mut dates) = match self.frequency {
+            Frequency::Daily => {
+                let day = start + Duration::days(i64::from(n));
+                let matches = (self.by_day.is_empty() || self.by_day.iter().any(|(_, wd)| *wd == day.weekday()))
+                    && (self.by_month_day.is_empty() || self.by_month_day.contains(&(day.day() as i32)));
+                (day, if matches { vec![day] } else { Vec::new() })
+            }
+            Frequency::Weekly => {
+                let monday = start - Duration::days(i64::from(start.weekday().num_days_from_monday()))
+                    + Duration::weeks(i64::from(n));
+                let weekdays = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|(_, weekday)| *weekday).collect()
+                };
+                let dates = weekdays
+                    .iter()
+                    .map(|weekday| monday + Duration::days(i64::from(weekday.num_days_from_monday())))
+                    .collect();
+                (monday, dates)
+            }
+            Frequency::Monthly => match add_months(n) {
+                Some(month) => (month, self.month_dates(month, start.day())),
+                None => return (NaiveDate::MAX, Vec::new()),
+            },
+            Frequency::Yearly => match NaiveDate::from_ymd_opt(start.year() + n as i32, 1, 1) {
+                Some(year) => {
+                    let months = if self.by_month.is_empty() { vec![start.month()] } else { self.by_month.clone() };
+                    let dates = months
+                        .iter()
+                        .filter_map(|month| year.with_month(*month))
+                        .flat_map(|month| self.month_dates(month, start.day()))
+                        .collect();
+                    (year, dates)
+                }
+                None => return (NaiveDate::MAX, Vec::new()),
+            },
+        };
+
+        dates.retain(|date| self.by_month.is_empty() || self.by_month.contains(&date.month()));
+        dates.sort();
+        dates.dedup();
+        (period_start, dates)
+    }
+
+    /// Get the dates in the month starting on `month` matching the rule,
+    /// defaulting to day `day` of the month.
+    fn month_dates(&self, month: NaiveDate, day: u32) -> Vec<NaiveDate> {
+        let days: Vec<NaiveDate> = month
+            .iter_days()
+            .take_while(|date| date.month() == month.month())
+            .collect();
+
+        let from_month_day = |n: i32| match n {
+            n if n > 0 => days.get(n as usize - 1).copied(),
+            n if n < 0 => days.len().checked_sub(n.unsigned_abs() as usize).and_then(|i| days.get(i)).copied(),
+            _ => None,
+        };
+
+        if !self.by_month_day.is_empty() {
+            return self
+                .by_month_day
+                .iter()
+                .filter_map(|n| from_month_day(*n))
+                .filter(|date| self.by_day.is_empty() || self.by_day.iter().any(|(_, wd)| *wd == date.weekday()))
+                .collect();
+        }
+
+        if self.by_day.is_empty() {
+            return from_month_day(day as i32).into_iter().collect();
+        }
+
+        self.by_day
+            .iter()
+            .flat_map(|(ordinal, weekday)| {
+                let matching: Vec<NaiveDate> = days.iter().copied().filter(|date| date.weekday() == *weekday).collect();
+                match *ordinal {
+                    0 => matching,
+                    n if n > 0 => matching.get(n as usize - 1).copied().into_iter().collect(),
+                    n => matching
+                        .len()
+                        .checked_sub(n.unsigned_abs() as usize)
+                        .and_then(|i| matching.get(i))
+                        .copied()
+                        .into_iter()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parse an iCalendar `RRULE` value, such as `FREQ=WEEKLY;BYDAY=MO,FR`.
+fn parse_rrule(rule: &str) -> Option<RecurrenceRule> {
+    let mut parsed = RecurrenceRule {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+        by_month_day: Vec::new(),
+        by_month: Vec::new(),
+    };
+    let mut frequency = None;
+
+    for (key, value) in rule.split(';').filter_map(|part| part.split_once('=')) {
+
+        match key.to_uppercase().as_str() {
+            "FREQ" => frequency = match value.to_uppercase().as_str() {
+                "DAILY" => Some(Frequency::Daily),
+                "WEEKLY" => Some(Frequency::Weekly),
+                "MONTHLY" => Some(Frequency::Monthly),
+                "YEARLY" => Some(Frequency::Yearly),
+                // Repeating more often than daily is not supported
+                _ => return None,
+            },
+            "INTERVAL" => parsed.interval = value.parse().unwrap_or(1),
+            "COUNT" => parsed.count = value.parse().ok(),
+            "UNTIL" => parsed.until = parse_ics_time(value, None).map(|(until, all_day, _)| {
+                // A date means the whole day is included
+                if all_day { until + Duration::days(1) - Duration::seconds(1) } else { until }
+            }),
+            "BYDAY" => parsed.by_day = value
+                .split(',')
+                .filter_map(|day| {
+                    let split = day.len().checked_sub(2)?;
+                    let weekday = match day.get(split..)?.to_uppercase().as_str() {
+                        "MO" => Weekday::Mon,
+                        "TU" => Weekday::Tue,
+                        "WE" => Weekday::Wed,
+                        "TH" => Weekday::Thu,
+                        "FR" => Weekday::Fri,
+                        "SA" => Weekday::Sat,
+                        "SU" => Weekday::Sun,
+                        _ => return None,
+                    };
+                    let ordinal = match &day[..split] {
+                        "" => 0,
+                        ordinal => ordinal.parse().ok()?,
+                    };
+                    Some((ordinal, weekday))
+                })
+                .collect(),
+            "BYMONTHDAY" => parsed.by_month_day = value.split(',').filter_map(|n| n.parse().ok()).collect(),
+            "BYMONTH" => parsed.by_month = value.split(',').filter_map(|n| n.parse().ok()).collect(),
+            _ => {}
+        }
+    }
+
+    parsed.frequency = frequency?;
+    Some(parsed)
+}
+
+/// Parse an iCalendar date or date-time, returning it with whether it is
+/// a date and the time zone it is in.
+fn parse_ics_time(value: &str, tzid: Option<&str>) -> Option<(NaiveDateTime, bool, EventZone)> {
+    let value = value.trim();
+
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((date.and_hms_opt(0, 0, 0)?, true, EventZone::Floating));
+    }
+
+    let (value, zone) = match (value.strip_suffix('Z'), tzid) {
+        (Some(value), _) => (value, EventZone::Utc),
+        (None, Some(tzid)) => (value, EventZone::Named(String::from(tzid.trim_start_matches('/')))),
+        (None, None) => (value, EventZone::Floating),
+    };
+
+    Some((NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?, false, zone))
+}
+
+/// Parse an iCalendar duration, such as `PT1H30M` or `P1D`.
+fn parse_ics_duration(value: &str) -> Option<Duration> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.trim_start_matches('+')),
+    };
+
+    let mut seconds = 0;
+    let mut number = String::new();
+
+    for c in value.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            _ => {
+                let n: i64 = number.parse().ok()?;
+                seconds += n * match c {
+                    'W' => 7 * 86400,
+                    'D' => 86400,
+                    'H' => 3600,
+                    'M' => 60,
+                    'S' => 1,
+                    _ => return None,
+                };
+                number.clear();
+            }
+        }
+    }
+
+    Some(Duration::seconds(sign * seconds))
+}
+
+/// Undo the escaping of commas, semicolons, backslashes and newlines in
+/// iCalendar text.
+fn unescape_ics(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') | Some('N') => unescaped.push(' '),
+                Some(escaped) => unescaped.push(escaped),
+                None => {}
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Convert a time in the time zone of an event to local time, with the
+/// time zones in `<root>`. Times in unknown time zones are taken to be local.
+fn local_time(root: &str, time: NaiveDateTime, zone: &EventZone) -> NaiveDateTime {
+    let utc = match zone {
+        EventZone::Floating => return time,
+        EventZone::Utc => time,
+        EventZone::Named(name) => {
+            // The offset is looked up at the local time, which is at most
+            // a few hours off from the time it should be looked up at
+            match zone_offset(root, name, time.and_utc().timestamp()) {
+                Some((offset, _)) => time - Duration::seconds(i64::from(offset)),
+                None => return time,
+            }
+        }
+    };
+
+    let (offset, _) = local_zone(root, utc.and_utc().timestamp());
+    utc + Duration::seconds(i64::from(offset))
+}
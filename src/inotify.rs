@@ -0,0 +1,73 @@
+//! Just enough inotify to be told when files change.
+
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+
+const EVENT_BUFFER: usize = 4096;
+
+/// Call `changed` in the background every time one of the events in `mask`
+/// happens to one of `paths`. Events arriving together only call it once.
+///
+/// The returned thread finishes if the paths can no longer be watched,
+/// for example when one of them is removed.
+pub fn watch<P: AsRef<Path>>(paths: &[P], mask: u32, changed: Box<dyn Fn() + Send>)
+    -> io::Result<JoinHandle<()>>
+{
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    for path in paths {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(thread::spawn(move || {
+        let mut buffer = [0u8; EVENT_BUFFER];
+
+        loop {
+            let read = unsafe {
+                libc::read(fd.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+            };
+            if read <= 0 {
+                return;
+            }
+
+            // A watched path going away means nothing more will be reported
+            if events(&buffer[..read as usize]).any(|mask| mask & libc::IN_IGNORED != 0) {
+                changed();
+                return;
+            }
+
+            changed();
+        }
+    }))
+}
+
+/// Get the masks of the events in a buffer read from an inotify descriptor.
+fn events(mut data: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    // struct inotify_event is wd, mask, cookie and len followed by the name
+    const HEADER: usize = 16;
+
+    std::iter::from_fn(move || {
+        if data.len() < HEADER {
+            return None;
+        }
+
+        let mask = u32::from_ne_bytes([data[4], data[5], data[6], data[7]]);
+        let length = u32::from_ne_bytes([data[12], data[13], data[14], data[15]]) as usize;
+        data = &data[(HEADER + length).min(data.len())..];
+
+        Some(mask)
+    })
+}
@@ -1,6 +1,7 @@
 //! This library contains the main functionality for creating a bar.
 
 extern crate chrono;
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
 
 extern crate i3ipc;
@@ -8,7 +9,43 @@ use i3ipc::reply::Node;
 use i3ipc::reply::NodeType;
 use i3ipc::I3Connection;
 
-use std::fs::read_to_string;
+extern crate libc;
+
+mod alsa;
+pub mod ical;
+mod inotify;
+pub mod netlink;
+pub mod pulse;
+mod sway;
+pub mod tzif;
+mod xkb;
+
+#[macro_use]
+extern crate log;
+
+extern crate serde_json;
+use serde_json::Value as Json;
+
+extern crate x11rb;
+
+extern crate zbus;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+use std::convert::TryFrom;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{read_dir, read_to_string};
+use std::ffi::{CStr, CString};
+use std::io::{self, stdout, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // Some icons for programs, in order of priority
 const FIREFOX: &str = "";
@@ -48,22 +85,60 @@ const LIGHTBROWN: &str = "#f4cf86";
 const ORANGE: &str = "#ffa724";
 const ERROR_COLOR: &str = RED;
 
-// Default colors (these are also defined in the start script)
-const BACKGROUND: &str = BLACK;
-const TEXT_COLOR: &str = WHITE;
+// Default colors (these are also used when `ba run` starts lemonbar)
+pub const BACKGROUND: &str = BLACK;
+pub const TEXT_COLOR: &str = WHITE;
+
+// Clicking a module toggles its detail mode. Set this to Some(seconds)
+// to go back to compact mode automatically after a while.
+const DETAIL_TIMEOUT: Option<u64> = None;
+
+// In debug mode, warn about modules taking longer than this (milliseconds)
+const MODULE_BUDGET: u64 = 50;
+
+// Root of the file system that /sys and /proc are read from
+const ROOT: &str = "/";
 
 // Network
-const WL_PATH: &str = "/sys/class/net/wlp3s0/";
 const WL_IND: &str = "";
-const ETH_PATH: &str = "/sys/class/net/enp2s0/";
 const ETH_IND: &str = "";
+const VPN_IND: &str = "";
 const NET_UP_COLOR: &str = GREEN;
 const NET_DOWN_COLOR: &str = RED;
+const NET_RX_IND: &str = "↓";
+const NET_TX_IND: &str = "↑";
+// Show rates in bits per second instead of bytes per second
+const NET_RATE_BITS: bool = false;
+// How much of the previous rate to keep, between 0.0 (no smoothing) and 1.0
+const NET_SMOOTHING: f64 = 0.5;
+// Show the IPv6 address as well as the IPv4 address
+const NET_SHOW_IPV6: bool = false;
+// Colors for the wireless icon depending on signal quality
+const WL_RANGES: [(&str, u32); 4] = [
+    (RED, 0),
+    (ORANGE, 25),
+    (LIGHTBROWN, 50),
+    (GREEN, 70),
+];
+
+// Vpn, the commands are run by i3 with VPN_CONNECTION when the icon is clicked
+const VPN_PREFIXES: [&str; 3] = ["wg", "tun", "tap"];
+const VPN_DOWN_IND: &str = "";
+const VPN_UP_COLOR: &str = GREEN;
+const VPN_DOWN_COLOR: &str = GREY;
+const VPN_STALE_COLOR: &str = ORANGE;
+const VPN_CONNECTION: &str = "vpn";
+const VPN_CONNECT: &str = "nmcli connection up";
+const VPN_DISCONNECT: &str = "nmcli connection down";
+// WireGuard tunnels without a handshake for this long are shown as stale (seconds)
+const VPN_STALE_HANDSHAKE: u64 = 180;
 
 // Battery
-const BAT_PATH: &str = "/sys/class/power_supply/BAT0/";
 const BAT_IND: &str = "";
 const BAT_CHARGING: &str = "";
+const BAT_FULL: &str = "";
+const BAT_NOT_CHARGING: &str = "";
+const BAT_UNKNOWN: &str = "";
 const BAT_RANGES: [(&str, u32); 5] = [
     (RED, 0),
     (ORANGE, 20),
@@ -71,6 +146,15 @@ const BAT_RANGES: [(&str, u32); 5] = [
     (WHITE, 50),
     (GREEN, 90),
 ];
+// Send a notification when the charge drops below these levels
+const BAT_ALERTS: [u32; 2] = [20, 10];
+// The charge has to rise this much above a level before it alerts again
+const BAT_ALERT_HYSTERESIS: u32 = 3;
+// Run BAT_CRITICAL_COMMAND after a countdown (seconds) at this level
+const BAT_CRITICAL: u32 = 5;
+const BAT_CRITICAL_COMMAND: &str = "systemctl suspend";
+const BAT_CRITICAL_COUNTDOWN: u64 = 60;
+const BAT_CRITICAL_COLOR: &str = RED;
 
 // Music
 const MU_PLAYERNAME: &str = "Spotify Premium";
@@ -79,8 +163,62 @@ const MU_IND: &str = "";
 const MU_IDLE_COLOR: &str = GREY;
 const MU_PLAY_COLOR: &str = ORANGE;
 
-// Time
+// Time, formatted like strftime with %: for a colored colon.
+// Weekday and month names follow the LC_TIME locale.
 const TI_COLON_COLOR: &str = LIGHTGREY;
+const TI_12_HOUR: bool = false;
+const TI_FORMAT: &str = if TI_12_HOUR { "%-I%:%M %p" } else { "%H%:%M" };
+const TI_DETAIL_FORMAT: &str = if TI_12_HOUR { "%-I%:%M %p %a %Y-%m-%d" } else { "%H%:%M %a %Y-%m-%d" };
+// Extra time zones, named as in /usr/share/zoneinfo, for example ["America/New_York", "Asia/Tokyo"]
+const TI_ZONES: [&str; 0] = [];
+const TI_ZONE_FORMAT: &str = if TI_12_HOUR { "%-I%:%M %p %Z" } else { "%H%:%M %Z" };
+// Cycle through the time zones by middle clicking, instead of listing them in detailed mode
+const TI_CYCLE_ZONES: bool = false;
+
+// Calendar, shown by clicking the clock. Events are read from an .ics file,
+// or from every .ics file in a directory, such as one synced by vdirsyncer
+const CAL_PATH: &str = "~/.local/share/calendars";
+// Run when the clock is clicked, showing `ba calendar`
+const CAL_CLIENT: &str = "xterm -hold -title ba-calendar -e ~/.local/bin/ba calendar";
+
+// Timer, see TimerMode. Clicking starts or pauses it and middle clicking resets it.
+const TM_IND: &str = "";
+const TM_MODE: TimerMode = TimerMode::Pomodoro;
+const TM_PAUSED_COLOR: &str = LIGHTGREY;
+const TM_EXPIRED_COLOR: &str = RED;
+// Pomodoro lengths (minutes), with a long break after every TM_LONG_BREAK_EVERY
+const TM_WORK: u64 = 25;
+const TM_BREAK: u64 = 5;
+const TM_LONG_BREAK: u64 = 15;
+const TM_LONG_BREAK_EVERY: u64 = 4;
+// Run when the timer runs out, in addition to a notification
+const TM_COMMAND: Option<&str> = None;
+
+// Mail, counting the messages in the new/ directory of each Maildir
+const MAIL_IND: &str = "";
+const MAIL_DIRS: [&str; 1] = ["~/Mail/INBOX"];
+// Commands printing a number of unread messages, for example ["notmuch count tag:unread"]
+const MAIL_COMMANDS: [&str; 0] = [];
+// Seconds between runs of the commands, which also run when a Maildir changes
+const MAIL_COMMAND_INTERVAL: u64 = 300;
+const MAIL_NONE_COLOR: &str = GREY;
+const MAIL_UNREAD_COLOR: &str = LIGHTBROWN;
+// Run when the icon is clicked
+const MAIL_CLIENT: &str = "xterm -e neomutt";
+
+// Notifications, see NotificationDaemon. Clicking toggles do-not-disturb.
+const NOTIF_DAEMON: NotificationDaemon = NotificationDaemon::Dunst;
+const NOTIF_IND: &str = "";
+const NOTIF_PAUSED_IND: &str = "";
+const NOTIF_PAUSED_COLOR: &str = GREY;
+// Longest summary of the latest notification shown in detailed mode
+const NOTIF_SUMMARY_LENGTH: usize = 40;
+
+// Systemd, hidden unless a unit has failed or a unit below is not active
+const SD_IND: &str = "";
+const SD_FAILED_COLOR: &str = RED;
+const SD_UNITS: [&str; 0] = [];
+const SD_USER_UNITS: [&str; 0] = [];
 
 // Workspace
 const WS_CURRENT: &str = GREY;
@@ -95,13 +233,100 @@ const CPU_RANGES: [(&str, u32); 5] = [
     (ORANGE, 40),
     (RED, 80),
 ];
+// Number of loads kept for the sparkline in detailed mode
+const CPU_HISTORY: usize = 16;
+// Draw sparklines with braille, fitting two loads in every character
+const CPU_BRAILLE: bool = false;
+
+// Memory
+const MEM_IND: &str = "";
+const MEM_RANGES: [(&str, u32); 4] = [
+    (TEXT_COLOR, 0),
+    (LIGHTBROWN, 50),
+    (ORANGE, 75),
+    (RED, 90),
+];
+// What counts as used memory, see MemUsed
+const MEM_USED: MemUsed = MemUsed::Unavailable;
+// Show swap, dirty memory and zram in detailed mode
+const MEM_SHOW_SWAP: bool = true;
+const MEM_SHOW_DIRTY: bool = false;
+const MEM_SHOW_ZRAM: bool = false;
+
+// Temperature, sensors are picked by label or chip name in order of priority
+const TEMP_IND: &str = "";
+const TEMP_SENSORS: [&str; 4] = ["Package id 0", "Tctl", "coretemp", "x86_pkg_temp"];
+const TEMP_FANS: [&str; 1] = ["cpu_fan"];
+const TEMP_RANGES: [(&str, u32); 4] = [
+    (TEXT_COLOR, 0),
+    (LIGHTBROWN, 60),
+    (ORANGE, 75),
+    (RED, 85),
+];
+// Underlined in this color when a sensor is above its critical temperature
+const TEMP_CRIT_COLOR: &str = RED;
+
+// Disk, mount points that are not mounted are left out
+const DISK_IND: &str = "";
+const DISK_MOUNTS: [&str; 2] = ["/", "/home"];
+const DISK_RANGES: [(&str, u32); 4] = [
+    (TEXT_COLOR, 0),
+    (LIGHTBROWN, 70),
+    (ORANGE, 85),
+    (RED, 95),
+];
+
+// Pressure, colored by the avg10 stall percentage of the worst resource
+const PSI_IND: &str = "";
+const PSI_RANGES: [(&str, u32); 3] = [
+    (TEXT_COLOR, 0),
+    (ORANGE, 10),
+    (RED, 30),
+];
+
+// Volume, see MixerBackend
+const VOL_BACKEND: MixerBackend = MixerBackend::Pulse;
+const VOL_IND: &str = "";
+const VOL_MUTED_IND: &str = "";
+const VOL_MUTED_COLOR: &str = GREY;
+// How much scrolling changes the volume (percent)
+const VOL_STEP: i32 = 5;
+// The ALSA card and mixer control to show
+const VOL_ALSA_CARD: u32 = 0;
+const VOL_ALSA_CONTROL: &str = "Master";
+
+// Backlight, the first device in /sys/class/backlight is used unless one is named
+const BL_IND: &str = "";
+const BL_DEVICE: Option<&str> = None;
+// How much scrolling changes the brightness (percent)
+const BL_STEP: i32 = 5;
+// Scrolling down stops here, so that the screen doesn't go dark (percent)
+const BL_MIN: i32 = 5;
+// Brightness is set through logind, or with this command if it is Some.
+// Any {} in the command is replaced by the new brightness in percent,
+// for example Some("brightnessctl set {}%")
+const BL_COMMAND: Option<&str> = None;
+
+// Keyboard, the layout is followed over X or, when $SWAYSOCK is set, sway
+const KB_IND: &str = "";
+const KB_OTHER_LAYOUT_COLOR: &str = LIGHTGREY;
+const KB_LOCK_COLOR: &str = ORANGE;
+// Sway names layouts by their description, these are shown as the short name
+const KB_NAMES: [(&str, &str); 2] = [
+    ("English (US)", "us"),
+    ("Swedish", "se"),
+];
 
-/// Create a module that can be stored in a bar from a function
+/// Create a module that can be stored in a bar from a function.
+///
+/// The module is named after the function, which is the name used
+/// to address it from click actions. Commands to run when the module
+/// is clicked can be given after the function.
 ///
 /// # Examples
 ///
 /// ```
-/// use ba::{Module, BarStr, barfn};
+/// use ba::{Module, BarStr, Button, barfn};
 ///
 /// fn updates(module: &mut Module<u64>) -> String {
 ///     module.data += 1;
@@ -109,15 +334,37 @@ const CPU_RANGES: [(&str, u32); 5] = [
 /// }
 /// let mut updates_module: Box<dyn BarStr> = barfn!(updates);
 ///
+/// assert_eq!(updates_module.name(), "updates");
 /// assert_eq!(updates_module.create_string(), "0");
 /// assert_eq!(updates_module.create_string(), "1");
 /// assert_eq!(updates_module.create_string(), "2");
+///
+/// let mut clickable: Box<dyn BarStr> = barfn!(updates, Button::Left => "exec st");
+/// assert_eq!(clickable.actions(), &[(Button::Left, String::from("exec st"))]);
+/// assert!(!clickable.send("reset"));
+///
+/// // Modules that handle messages, see `Module::messages`, say so
+/// let mut listening: Box<dyn BarStr> = barfn!(updates, messages, Button::Left => "ba updates reset");
+/// assert!(listening.send("reset"));
 /// ```
 #[macro_export]
 macro_rules! barfn {
-    ( $($f:expr)? ) => {
-        $(Box::new(Module::new($f)))?
+    ( $f:expr, messages $(, $button:expr => $command:expr)* ) => {
+        Box::new(Module::new($f).with_name(stringify!($f)).with_messages()$(.on_click($button, $command))*)
     };
+    ( $f:expr $(, $button:expr => $command:expr)* ) => {
+        Box::new(Module::new($f).with_name(stringify!($f))$(.on_click($button, $command))*)
+    };
+}
+
+/// Mouse buttons lemonbar can react to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Left = 1,
+    Middle = 2,
+    Right = 3,
+    ScrollUp = 4,
+    ScrollDown = 5,
 }
 
 /// Trait for structs that represent a bar or something on the bar.
@@ -130,8 +377,40 @@ pub trait BarStr: Send {
 
     /// Return `true` if the object outputs detailed information.
     fn is_detailed(&self) -> bool;
+
+    /// The name used to address the object from click actions.
+    /// Objects without a name can not be clicked.
+    fn name(&self) -> &str {
+        ""
+    }
+
+    /// Commands to run when the object is clicked with a certain button.
+    fn actions(&self) -> &[(Button, String)] {
+        &[]
+    }
+
+    /// Pass a message to the object, such as `up` from `ba volume up`.
+    /// Returns `false` if the object doesn't take messages.
+    fn send(&mut self, _message: &str) -> bool {
+        false
+    }
+}
+
+/// Used by modules to redraw the bar when something changes,
+/// instead of waiting for the next update.
+static REDRAW: Mutex<Option<Sender<()>>> = Mutex::new(None);
+
+/// Set where redraw requests from modules are sent.
+pub fn set_redraw_sender(sender: Sender<()>) {
+    *REDRAW.lock().unwrap_or_else(|e| e.into_inner()) = Some(sender);
 }
 
+/// Ask for the bar to be redrawn as soon as possible.
+pub fn request_redraw() {
+    if let Some(sender) = REDRAW.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        let _ = sender.send(());
+    }
+}
 
 /// A container which can print formated data for Lemonbar.
 ///
@@ -145,19 +424,27 @@ pub trait BarStr: Send {
 ///     (module.data - 1).to_string()
 /// }
 ///
-/// let mut bar = Bar {
-///     left: vec![barfn!(updates)],
-///     center: vec![],
-///     right: vec![]
-/// };
+/// let mut bar = Bar::new(
+///     vec![barfn!(updates)],
+///     vec![],
+///     vec![]
+/// );
 ///
 /// bar.output_data();
+///
+/// // Clicking a module toggles detail mode for that module only
+/// assert!(bar.toggle_module("updates"));
+/// assert!(bar.left[0].is_detailed());
+/// assert!(!bar.is_detailed());
 /// ```
 pub struct Bar {
     pub left: Vec<Box<dyn BarStr>>,
     pub center: Vec<Box<dyn BarStr>>,
     pub right: Vec<Box<dyn BarStr>>,
     detailed: bool,
+    deadlines: HashMap<String, Instant>,
+    output: Box<dyn Write + Send>,
+    debug: bool,
 }
 
 impl Bar {
@@ -166,49 +453,170 @@ impl Bar {
         center: Vec<Box<dyn BarStr>>,
         right: Vec<Box<dyn BarStr>>) -> Bar
     {
-        Bar{left: left, center: center, right: right, detailed: false}
+        Bar {
+            left,
+            center,
+            right,
+            detailed: false,
+            deadlines: HashMap::new(),
+            output: Box::new(stdout()),
+            debug: false,
+        }
+    }
+
+    /// Log how long each module takes to create its string.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Send the output of the bar somewhere other than stdout.
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+
+    /// Toggle detail mode for the module called `name`.
+    /// Returns `false` if there is no such module.
+    ///
+    /// If `DETAIL_TIMEOUT` is set, the module goes back
+    /// to compact mode once the timeout has passed.
+    pub fn toggle_module(&mut self, name: &str) -> bool {
+        let mut found = false;
+
+        for module in self.modules_mut().filter(|m| m.name() == name) {
+            let detailed = !module.is_detailed();
+            module.set_detailed(detailed);
+            found = true;
+        }
+
+        self.deadlines.remove(name);
+        if let Some(timeout) = DETAIL_TIMEOUT {
+            if found && !self.detailed {
+                let deadline = Instant::now() + Duration::from_secs(timeout);
+                self.deadlines.insert(String::from(name), deadline);
+            }
+        }
+
+        found
+    }
+
+    /// Pass a message to the module called `name`.
+    /// Returns `false` if there is no such module taking messages.
+    pub fn send_module(&mut self, name: &str, message: &str) -> bool {
+        let mut found = false;
+
+        for module in self.modules_mut().filter(|m| m.name() == name) {
+            found |= module.send(message);
+        }
+
+        found
+    }
+
+    /// Iterate over all modules on the bar, from left to right.
+    fn modules_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn BarStr>> {
+        self.left
+            .iter_mut()
+            .chain(self.center.iter_mut())
+            .chain(self.right.iter_mut())
+    }
+
+    /// Put modules whose detail timeout has passed back into compact mode.
+    fn expire_details(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<String> = self.deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in expired {
+            self.deadlines.remove(&name);
+            for module in self.modules_mut().filter(|m| m.name() == name) {
+                module.set_detailed(false);
+            }
+        }
     }
 
     /// Output a formatted string representing the content of the bar.
     pub fn output_data(&mut self) {
-        println!("{}", self.create_string());
+        let string = self.create_string();
+
+        // Lemonbar might have gone away, it is restarted elsewhere
+        let _ = writeln!(self.output, "{}", string).and_then(|_| self.output.flush());
     }
 
     /// Construct a string with the string representations
     /// of all Modules in a Vector, separated by sep.
-    fn join_modules(modules: &mut Vec<Box<dyn BarStr>>, sep: &str) -> String {
+    ///
+    /// Named modules are made clickable. Right clicking toggles their
+    /// detail mode, other buttons run the actions of the module.
+    /// Modules returning an empty string are hidden.
+    fn join_modules(modules: &mut [Box<dyn BarStr>], sep: &str, debug: bool) -> String {
         modules
             .iter_mut()
-            .map(|m| m.create_string())
+            .map(|m| (Bar::create_module_string(m, debug), m))
+            // Modules with nothing to show are left out
+            .filter(|(string, _)| !string.is_empty())
+            .map(|(mut string, m)| {
+                if m.name().is_empty() {
+                    return string;
+                }
+
+                for (button, command) in m.actions() {
+                    string = buttonize_on(&string, *button, command);
+                }
+
+                let detail = format!("ba detail {}", m.name());
+                buttonize_on(&string, Button::Right, &detail)
+            })
             .collect::<Vec<String>>()
             .join(sep)
     }
+
+    /// Create the string of a single module.
+    /// A module that panics shows an error instead of taking the bar down.
+    fn create_module_string(module: &mut Box<dyn BarStr>, debug: bool) -> String {
+        let start = Instant::now();
+        let result = catch_unwind(AssertUnwindSafe(|| module.create_string()));
+        let elapsed = start.elapsed();
+
+        if debug {
+            debug!("Module {} took {:?}", module.name(), elapsed);
+            if elapsed > Duration::from_millis(MODULE_BUDGET) {
+                warn!("Module {} took {:?}, more than {} ms",
+                    module.name(), elapsed, MODULE_BUDGET);
+            }
+        }
+
+        result.unwrap_or_else(|_| {
+            error!("Module {} panicked", module.name());
+            paint(&format!("ERROR: {}", module.name()), ERROR_COLOR, "F")
+        })
+    }
 }
 
 impl BarStr for Bar {
 
     fn create_string(&mut self) -> String {
+        self.expire_details();
+
         let begin = "";
         let end = " ";
         let separator = " ";
 
-        let left = format!("%{{l}}{}", Bar::join_modules(&mut self.left, separator));
-        let center = format!("%{{c}}{}", Bar::join_modules(&mut self.center, separator));
-        let right = format!("%{{r}}{}", Bar::join_modules(&mut self.right, separator));
+        let debug = self.debug;
+        let left = format!("%{{l}}{}", Bar::join_modules(&mut self.left, separator, debug));
+        let center = format!("%{{c}}{}", Bar::join_modules(&mut self.center, separator, debug));
+        let right = format!("%{{r}}{}", Bar::join_modules(&mut self.right, separator, debug));
 
         format!("{}{}{}{}{}", begin, left, center, right, end)
     }
 
     fn set_detailed(&mut self, detailed: bool) {
-        for module in self
-            .left
-            .iter_mut()
-            .chain(self.center.iter_mut())
-            .chain(self.right.iter_mut())
-        {
+        for module in self.modules_mut() {
             module.set_detailed(detailed);
         }
 
+        self.deadlines.clear();
         self.detailed = detailed;
     }
 
@@ -222,22 +630,67 @@ impl BarStr for Bar {
 /// It consists of two main components:
 /// * A function returning the string to show on the bar
 /// * A data field for storing data between updates
+///
+/// Messages sent to the module are kept until the function has run,
+/// so the function can handle them with `messages`.
 #[derive(Clone)]
 pub struct Module<T> {
     function: fn(&mut Module<T>) -> String,
     pub data: T,
     detailed: bool,
+    name: String,
+    actions: Vec<(Button, String)>,
+    takes_messages: bool,
+    messages: Vec<String>,
 }
 
 impl<T: Default> Module<T> {
     pub fn new(f: fn(&mut Module<T>) -> String) -> Module<T> {
-        Module{function: f, data: Default::default(), detailed: false}
+        Module {
+            function: f,
+            data: Default::default(),
+            detailed: false,
+            name: String::new(),
+            actions: Vec::new(),
+            takes_messages: false,
+            messages: Vec::new(),
+        }
+    }
+}
+
+impl<T> Module<T> {
+    /// Give the module a name, making it possible to click on it.
+    pub fn with_name(mut self, name: &str) -> Module<T> {
+        self.name = String::from(name);
+        self
+    }
+
+    /// Run `command` when the module is clicked with `button`.
+    ///
+    /// Commands starting with `ba ` are handled by the bar,
+    /// anything else is sent to i3.
+    pub fn on_click(mut self, button: Button, command: &str) -> Module<T> {
+        self.actions.push((button, String::from(command)));
+        self
+    }
+
+    /// Accept messages sent to the module, which it reads with `messages`.
+    pub fn with_messages(mut self) -> Module<T> {
+        self.takes_messages = true;
+        self
+    }
+
+    /// Take the messages sent to the module since it last ran.
+    pub fn messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
     }
 }
 
 impl<T: Send> BarStr for Module<T> {
     fn create_string(&mut self) -> String {
-        (self.function)(self)
+        let string = (self.function)(self);
+        self.messages.clear();
+        string
     }
 
     fn set_detailed(&mut self, detailed: bool) {
@@ -247,6 +700,21 @@ impl<T: Send> BarStr for Module<T> {
     fn is_detailed(&self) -> bool {
         self.detailed
     }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn actions(&self) -> &[(Button, String)] {
+        &self.actions
+    }
+
+    fn send(&mut self, message: &str) -> bool {
+        if self.takes_messages {
+            self.messages.push(String::from(message));
+        }
+        self.takes_messages
+    }
 }
 
 /*         */
@@ -254,59 +722,131 @@ impl<T: Send> BarStr for Module<T> {
 /*         */
 
 /// Create a lemonbar-formatted `String` representing the current time.
-pub fn time(module: &mut Module<()>) -> String {
-    let colon = paint(":", TI_COLON_COLOR, "F");
-    let now = Local::now();
+///
+/// The time zones in `TI_ZONES` are listed in detailed mode, or with
+/// `TI_CYCLE_ZONES` shown one at a time, switching on the message `next`.
+/// The message `calendar` runs `CAL_CLIENT`, see `calendar`.
+///
+/// ### Stored data
+/// * The shown time zone, 0 for local time and otherwise `TI_ZONES[n - 1]`
+pub fn time(module: &mut Module<usize>) -> String {
+    let now = Utc::now().timestamp();
 
-    if module.is_detailed() {
-        format!("{:02}{}{:02} {:?} {}-{:02}-{:02}",
-            now.hour(),
-            colon,
-            now.minute(),
-            now.weekday(),
-            now.year(),
-            now.month(),
-            now.day())
-    } else {
-        format!("{:02}{}{:02}", now.hour(), colon, now.minute())
+    for message in module.messages() {
+        match message.as_str() {
+            "next" => module.data = (module.data + 1) % (TI_ZONES.len() + 1),
+            "calendar" => run_command(CAL_CLIENT),
+            _ => warn!("Unknown time message '{}'", message),
+        }
+    }
+
+    let zone = |name: &str| {
+        let zone = zone_offset(ROOT, name, now);
+        if zone.is_none() {
+            debug!("Unknown time zone {}", name);
+        }
+        zone
+    };
+
+    if TI_CYCLE_ZONES && module.data > 0 {
+        if let Some((offset, abbreviation)) = zone(TI_ZONES[module.data - 1]) {
+            let format = if module.is_detailed() {
+                format!("{} %Z", TI_DETAIL_FORMAT)
+            } else {
+                String::from(TI_ZONE_FORMAT)
+            };
+            return format_time(&format, now, Some((offset, &abbreviation)));
+        }
+    }
+
+    if !module.is_detailed() {
+        return format_time(TI_FORMAT, now, None);
+    }
+
+    let mut ret = vec![format_time(TI_DETAIL_FORMAT, now, None)];
+
+    if !TI_CYCLE_ZONES {
+        for (offset, abbreviation) in TI_ZONES.iter().filter_map(|name| zone(name)) {
+            ret.push(format_time(TI_ZONE_FORMAT, now, Some((offset, &abbreviation))));
+        }
     }
+
+    ret.join(" ")
 }
 
 /// Create a lemonbar-formatted `String` representing battery status.
-pub fn battery(module: &mut Module<()>) -> String {
-    let capacity_path = String::from(BAT_PATH) + "capacity";
-    let status_path = String::from(BAT_PATH) + "status";
+///
+/// All batteries are combined into one. In detailed mode the time
+/// until the batteries are empty or full is shown as well.
+/// Nothing is shown if there are no batteries.
+///
+/// A notification is sent when the charge drops below the levels in
+/// `BAT_ALERTS`. When it drops below `BAT_CRITICAL`, a critical notification
+/// says when `BAT_CRITICAL_COMMAND` will run, which it does after a countdown.
+///
+/// ### Stored data
+/// * `alerts: BatAlerts`
+pub fn battery(module: &mut Module<BatAlerts>) -> String {
+    let battery = match read_batteries(ROOT) {
+        Some(battery) => battery,
+        None => return String::new(),
+    };
 
-    // Read capacity
-    let capacity_string =
-        read_to_string(capacity_path)
-        .expect("Unable to open battery capacity: Invalid path");
+    let discharging = battery.status == BatStatus::Discharging;
 
-    let capacity: u32 = capacity_string.trim().parse().unwrap();
+    if module.data.check(battery.capacity, discharging).is_some() {
+        notify("Battery low", &format!("{}% left", battery.capacity), false);
+    }
 
-    // Read status
-    let status = read_to_string(status_path)
-        .expect("Unable to open battery status: Invalid path");
+    let counting = module.data.critical_at.is_some();
+    let countdown = module.data.countdown(battery.capacity, discharging, Instant::now());
 
-    let icon = if status.trim() == "Discharging" {
-        BAT_IND
-    } else {
-        BAT_CHARGING
+    if let (false, Some(left)) = (counting, countdown) {
+        let deadline = Local::now() + chrono::Duration::from_std(left).unwrap_or_default();
+        notify(
+            "Battery critical",
+            &format!("{}% left, running '{}' at {} unless plugged in",
+                battery.capacity, BAT_CRITICAL_COMMAND, deadline.format("%H:%M:%S")),
+            true,
+        );
+    }
+
+    let icon = match battery.status {
+        BatStatus::Charging => BAT_CHARGING,
+        BatStatus::Discharging => BAT_IND,
+        BatStatus::Full => BAT_FULL,
+        BatStatus::NotCharging => BAT_NOT_CHARGING,
+        BatStatus::Unknown => BAT_UNKNOWN,
     };
 
     // Assign color depending on capacity
-    for (color, thresh) in BAT_RANGES.iter().rev() {
-        if capacity >= *thresh {
-            let ret = paint(icon, color, "F");
-            if module.is_detailed() {
-                return format!("{} {}", ret, capacity);
-            } else {
-                return ret;
+    let ret = paint(icon, range_color(&BAT_RANGES, battery.capacity), "F");
+
+    // The countdown is always shown, so that there is time to plug in
+    if let Some(left) = countdown {
+        if left == Duration::from_secs(0) {
+            if !module.data.critical_done {
+                module.data.critical_done = true;
+                warn!("Battery critical, running '{}'", BAT_CRITICAL_COMMAND);
+                run_command(BAT_CRITICAL_COMMAND);
             }
+        } else {
+            let text = format!("{} in {}", BAT_CRITICAL_COMMAND, human_duration(left));
+            return format!("{} {}", ret, paint(&text, BAT_CRITICAL_COLOR, "F"));
         }
     }
 
-    unreachable!();
+    if !module.is_detailed() {
+        return ret;
+    }
+
+    match battery.time_left {
+        Some(time) => {
+            let minutes = time.as_secs() / 60;
+            format!("{} {} {}:{:02}", ret, battery.capacity, minutes / 60, minutes % 60)
+        }
+        None => format!("{} {}", ret, battery.capacity),
+    }
 }
 
 /// Create a lemonbar-formatted `String` representing workspaces.
@@ -355,7 +895,7 @@ pub fn workspaces(module: &mut Module<i64>) -> String {
             // Get node name
             let node_name = match node.name {
                 Some(n) => {
-                    if &n == MU_PLAYERNAME || module.data == node.id {
+                    if n == MU_PLAYERNAME || module.data == node.id {
                         module.data = node.id;
                         music_found = true;
                         String::from(MU_PLAYERNAME)
@@ -422,28 +962,144 @@ pub fn workspaces(module: &mut Module<i64>) -> String {
 }
 
 /// Create a lemonbar-formatted `String` representing network connection.
-pub fn network(_data: &mut Module<()>) -> String {
-    // Read the operstate file to see if the wireless is up
-    let status_path = String::from(WL_PATH) + "operstate";
-    let status = read_to_string(status_path);
-    if status.is_err() {
-        return paint("ERROR: wireless status could not be read", ERROR_COLOR, "F");
+///
+/// The icon shows the interface with the default route, or any other
+/// interface that is up if there is no default route. In detailed mode
+/// the address and the download and upload rates of the interface are shown.
+///
+/// ### Stored data
+/// * `stats: NetStats`
+pub fn network(module: &mut Module<NetStats>) -> String {
+    let interfaces = read_interfaces(ROOT);
+
+    let interface = match active_interface(&interfaces, default_route_interface(ROOT)) {
+        Some(interface) => interface,
+        None => {
+            module.data = NetStats::default();
+
+            // Show the kind of connection that is missing
+            let wireless = interfaces.iter().any(|i| i.kind == NetKind::Wireless);
+            let ethernet = interfaces.iter().any(|i| i.kind == NetKind::Ethernet);
+            let icon = if ethernet && !wireless { ETH_IND } else { WL_IND };
+            return paint(icon, NET_DOWN_COLOR, "F");
+        }
+    };
+
+    // Sample the counters on every update to have rates ready for detailed mode
+    if let Some((rx_bytes, tx_bytes)) = read_net_bytes(ROOT, &interface.name) {
+        module.data.update(&interface.name, rx_bytes, tx_bytes, Instant::now());
     }
-    let status = status.unwrap();
 
+    // Wireless interfaces are colored by signal quality
+    let signal = if interface.kind == NetKind::Wireless {
+        read_to_string(Path::new(ROOT).join("proc/net/wireless"))
+            .ok()
+            .and_then(|wireless| parse_wireless(&wireless, &interface.name))
+    } else {
+        None
+    };
+
+    let color = match signal {
+        Some(signal) => range_color(&WL_RANGES, signal.quality),
+        None => NET_UP_COLOR,
+    };
 
-    if status.trim() == "up" {
-        return paint(WL_IND, NET_UP_COLOR, "F");
+    let icon = paint(net_icon(interface.kind), color, "F");
+    if !module.is_detailed() {
+        return icon;
     }
 
-    let status_path = String::from(ETH_PATH) + "operstate";
-    let status = read_to_string(status_path).expect("Failed to read wireless status");
+    let mut details = vec![icon];
 
-    if status.trim() == "up" {
-        paint(ETH_IND, NET_UP_COLOR, "F")
-    } else {
-        paint(WL_IND, NET_DOWN_COLOR, "F")
+    if interface.kind == NetKind::Wireless {
+        match netlink::wireless_ssid(&interface.name) {
+            Ok(Some(ssid)) => details.push(escape(&ssid)),
+            Ok(None) => {}
+            Err(e) => debug!("Failed to get SSID of {}: {}", interface.name, e),
+        }
+    }
+    if let Some(signal) = signal {
+        details.push(format!("{}%", signal.quality));
+    }
+    let addresses = interface_addresses(&interface.name);
+    let ipv4 = addresses.iter().find(|a| a.is_ipv4());
+    let ipv6 = addresses.iter().find(|a| match a {
+        // Skip link-local addresses, fe80::/10
+        IpAddr::V6(a) => a.segments()[0] & 0xffc0 != 0xfe80,
+        IpAddr::V4(_) => false,
+    });
+
+    if let Some(ip) = ipv4 {
+        details.push(ip.to_string());
+    }
+    if let Some(ip) = ipv6.filter(|_| NET_SHOW_IPV6 || ipv4.is_none()) {
+        details.push(ip.to_string());
+    }
+
+    details.push(format!("{}{} {}{}",
+        human_rate(module.data.rx_rate), NET_RX_IND,
+        human_rate(module.data.tx_rate), NET_TX_IND));
+
+    details.join(" ")
+}
+
+/// Create a lemonbar-formatted `String` representing VPN tunnels.
+///
+/// Clicking the icon connects or disconnects the VPN.
+/// In detailed mode the tunnels that are up are listed,
+/// with the time since the last handshake for WireGuard tunnels.
+pub fn vpn(module: &mut Module<()>) -> String {
+    let tunnels: Vec<Interface> = read_interfaces(ROOT)
+        .into_iter()
+        .filter(|i| i.up && i.kind == NetKind::Vpn)
+        .filter(|i| VPN_PREFIXES.iter().any(|p| i.name.starts_with(p)))
+        .collect();
+
+    if tunnels.is_empty() {
+        let icon = paint(VPN_DOWN_IND, VPN_DOWN_COLOR, "F");
+        return buttonize(&icon, &format!("exec {} {}", VPN_CONNECT, VPN_CONNECTION));
+    }
+
+    let mut stale = false;
+    let mut details = Vec::with_capacity(tunnels.len());
+
+    for tunnel in &tunnels {
+        if !tunnel.name.starts_with("wg") {
+            details.push(tunnel.name.clone());
+            continue;
+        }
+
+        // Reading WireGuard peers needs privileges, so this often fails
+        let since = match netlink::wireguard_last_handshake(&tunnel.name) {
+            Ok(Some(time)) => time.elapsed().ok(),
+            Ok(None) => None,
+            Err(e) => {
+                debug!("Failed to read handshake of {}: {}", tunnel.name, e);
+                details.push(tunnel.name.clone());
+                continue;
+            }
+        };
+
+        match since {
+            Some(since) => {
+                stale |= since.as_secs() > VPN_STALE_HANDSHAKE;
+                details.push(format!("{} {}", tunnel.name, human_duration(since)));
+            }
+            None => {
+                stale = true;
+                details.push(tunnel.name.clone());
+            }
+        }
+    }
+
+    let color = if stale { VPN_STALE_COLOR } else { VPN_UP_COLOR };
+    let mut string = paint(VPN_IND, color, "F");
+
+    if module.is_detailed() {
+        string = format!("{} {}", string, details.join(" "));
     }
+
+    buttonize(&string, &format!("exec {} {}", VPN_DISCONNECT, VPN_CONNECTION))
 }
 
 /// Create a lemonbar-formatted `String` representing playing music.
@@ -467,7 +1123,7 @@ pub fn music(module: &mut Module<i64>) -> String {
     }
 
     // Return if no music is playing
-    if &window_name == MU_PLAYERNAME {
+    if window_name == MU_PLAYERNAME {
         return paint(MU_IND, MU_IDLE_COLOR, "F");
     }
 
@@ -484,100 +1140,3572 @@ pub fn music(module: &mut Module<i64>) -> String {
 
 /// Create a lemonbar-formatted `String` representing the cpu.
 ///
+/// In detailed mode the load is shown together with a sparkline of
+/// recent loads, the load of each core and the current frequency.
+///
 /// ### Stored data
-/// * `idle_time: u64`
-/// * `total_time: u64`
-pub fn cpu(module: &mut Module<(u64, u64)>) -> String {
+/// * `stats: CpuStats`
+pub fn cpu(module: &mut Module<CpuStats>) -> String {
     // Read cpu values from /proc/stat
-    let stats = read_to_string("/proc/stat")
-        .expect("Failed to read procfile.")
-        .split(" ")
-        .skip(2)
-        .take(7)
-        .map(|x| x.parse().unwrap())
-        .collect::<Vec<u64>>();
+    let stat = match read_to_string(Path::new(ROOT).join("proc/stat")) {
+        Ok(stat) => stat,
+        Err(e) => {
+            warn!("Failed to read /proc/stat: {}", e);
+            return paint(CP_IND, ERROR_COLOR, "F");
+        }
+    };
+
+    let loads = module.data.update(parse_proc_stat(&stat));
+    let load = loads.first().copied().unwrap_or(0);
 
-    let (last_idle, last_total) = module.data;
-    let (idle, total) = (stats[3], stats.iter().sum());
+    // Assign color depending on cpu load
+    let ret = paint(CP_IND, range_color(&CPU_RANGES, load), "F");
 
-    module.data = (idle, total);
+    if !module.is_detailed() {
+        return ret;
+    }
 
-    let idle_ratio = (idle - last_idle) as f64 / (total - last_total) as f64;
-    let load = 100 - (idle_ratio * 100.0).round() as u32;
+    let history: Vec<u32> = module.data.history.iter().copied().collect();
+    let mut details = vec![ret, load.to_string(), sparkline(&history)];
 
-    // Assign color depending on cpu load
-    for (color, load_thres) in CPU_RANGES.iter().rev() {
-        if load >= *load_thres {
-            let ret = paint(CP_IND, color, "F");
-            if module.is_detailed() {
-                return format!("{} {}", ret, load);
-            } else {
-                return ret;
-            }
-        }
+    if loads.len() > 2 {
+        details.push(sparkline(&loads[1..]));
+    }
+    if let Some(frequency) = cpu_frequency(ROOT) {
+        details.push(format!("{:.1}GHz", frequency / 1e6));
     }
 
-    unreachable!();
+    details.join(" ")
 }
 
-/*                  */
-/* Helper Functions */
-/*                  */
+/// Create a lemonbar-formatted `String` representing memory usage.
+///
+/// In detailed mode the used and total memory is shown,
+/// followed by swap and optionally dirty memory and zram.
+pub fn memory(module: &mut Module<()>) -> String {
+    let meminfo = match read_to_string(Path::new(ROOT).join("proc/meminfo")) {
+        Ok(meminfo) => parse_meminfo(&meminfo),
+        Err(e) => {
+            warn!("Failed to read /proc/meminfo: {}", e);
+            return paint(MEM_IND, ERROR_COLOR, "F");
+        }
+    };
 
-/// Search a i3 tree for a node with a certain name or id.
-fn get_node_from_name_or_id(node: Node, name: &str, id: i64) -> Option<Node> {
-    let t = String::new();
-    let node_name = node.name.as_ref().unwrap_or(&t);
+    let total = meminfo.get("MemTotal").copied().unwrap_or(0);
+    let used = MEM_USED.used(&meminfo);
+    let percent = (used * 100).checked_div(total).unwrap_or(0) as u32;
 
-    if node.id == id || node_name == name {
-        return Some(node);
+    let ret = paint(MEM_IND, range_color(&MEM_RANGES, percent), "F");
+
+    if !module.is_detailed() {
+        return ret;
     }
 
-    for n in node.nodes {
-        if let Some(res) = get_node_from_name_or_id(n, name, id) {
-            return Some(res);
+    // Sizes in /proc/meminfo are in KiB
+    let size = |kib: u64| human_bytes(kib as f64 * 1024.0);
+    let mut details = vec![ret, format!("{}/{}", size(used), size(total))];
+
+    let swap_total = meminfo.get("SwapTotal").copied().unwrap_or(0);
+    if MEM_SHOW_SWAP && swap_total > 0 {
+        let swap_used = swap_total.saturating_sub(meminfo.get("SwapFree").copied().unwrap_or(0));
+        details.push(format!("swap {}/{}", size(swap_used), size(swap_total)));
+    }
+    if MEM_SHOW_DIRTY {
+        details.push(format!("dirty {}", size(meminfo.get("Dirty").copied().unwrap_or(0))));
+    }
+    if MEM_SHOW_ZRAM {
+        if let Some(zram) = zram_used(ROOT) {
+            details.push(format!("zram {}", human_bytes(zram as f64)));
         }
     }
 
-    None
+    details.join(" ")
 }
 
-/// Get all workspaces in a i3 tree.
-fn get_workspaces(data: &mut Vec<Node>, node: Node) {
-    if node.nodetype == NodeType::Workspace {
-        data.push(node);
-    } else {
-        for n in node.nodes {
-            get_workspaces(data, n);
+/// Create a lemonbar-formatted `String` representing temperature.
+///
+/// The sensor is picked from `TEMP_SENSORS`, and the icon is underlined
+/// when it passes its critical temperature. In detailed mode the
+/// temperature and fan speed are shown. Nothing is shown without sensors.
+pub fn temperature(module: &mut Module<()>) -> String {
+    let sensors = read_temperatures(ROOT);
+    let sensor = match pick_sensor(&sensors, &TEMP_SENSORS) {
+        Some(sensor) => sensor,
+        None => return String::new(),
+    };
+
+    let mut ret = paint(TEMP_IND, range_color(&TEMP_RANGES, sensor.celsius.max(0.0) as u32), "F");
+
+    if module.is_detailed() {
+        ret = format!("{} {:.0}°C", ret, sensor.celsius);
+
+        let fans = read_fans(ROOT);
+        let fan = TEMP_FANS
+            .iter()
+            .find_map(|label| fans.iter().find(|(l, _)| l == label))
+            .or_else(|| fans.first());
+        if let Some((_, rpm)) = fan {
+            ret = format!("{} {}rpm", ret, rpm);
         }
     }
-}
 
-/// Put all nodes in a i3 tree `node` into the vector `data`.
-fn get_nodes(data: &mut Vec<Node>, node: Node) {
-    if node.nodes.len() == 0
-        && node.floating_nodes.len() == 0
-        && node.nodetype != NodeType::Workspace
-    {
-        data.push(node);
-    } else {
-        for n in node.nodes {
-            get_nodes(data, n);
-        }
-        for n in node.floating_nodes {
-            get_nodes(data, n);
-        }
+    if sensor.critical.is_some_and(|critical| sensor.celsius >= critical) {
+        ret = paint(&ret, TEMP_CRIT_COLOR, "U");
     }
+
+    ret
 }
 
-/// Helper function for changing colors on the bar.
-pub fn paint(string: &str, color: &str, layer: &str) -> String {
-    let mut to_paint = String::from(string);
+/// Create a lemonbar-formatted `String` representing disk usage.
+///
+/// The icon is colored by the fullest mount point in `DISK_MOUNTS`.
+/// In detailed mode every mount point is listed with how full it is,
+/// followed by the read and write rates of their disks.
+///
+/// ### Stored data
+/// * `stats: DiskStats`
+pub fn disk(module: &mut Module<DiskStats>) -> String {
+    let mounts = read_to_string(Path::new(ROOT).join("proc/self/mounts"))
+        .map(|mounts| parse_mounts(&mounts))
+        .unwrap_or_default();
 
-    let default_color = if layer == "F" {
-        TEXT_COLOR
-    } else {
-        if layer == "U" {
+    // Mount points come and go, for example USB drives
+    let mounted: Vec<&(String, String)> = DISK_MOUNTS
+        .iter()
+        .filter_map(|point| mounts.iter().rev().find(|(_, p)| p == point))
+        .collect();
+
+    let usage: Vec<(&str, u32)> = mounted
+        .iter()
+        .filter_map(|(_, point)| Some((point.as_str(), disk_usage(point)?)))
+        .collect();
+
+    if usage.is_empty() {
+        return String::new();
+    }
+
+    // Sample the counters on every update to have rates ready for detailed mode
+    let devices = distinct_disks(ROOT, mounted.iter().map(|(device, _)| block_device(device)).collect());
+    if let Ok(diskstats) = read_to_string(Path::new(ROOT).join("proc/diskstats")) {
+        let diskstats = parse_diskstats(&diskstats);
+        let (read, written) = devices
+            .iter()
+            .filter_map(|device| diskstats.get(device))
+            .fold((0, 0), |(r, w), (read, written)| (r + read, w + written));
+        module.data.update(read, written, Instant::now());
+    }
+
+    let fullest = usage.iter().map(|(_, percent)| *percent).max().unwrap_or(0);
+    let ret = paint(DISK_IND, range_color(&DISK_RANGES, fullest), "F");
+
+    if !module.is_detailed() {
+        return ret;
+    }
+
+    let mut details = vec![ret];
+    details.extend(usage.iter().map(|(point, percent)| format!("{} {}%", point, percent)));
+    details.push(format!("R {} W {}",
+        human_rate(module.data.read_rate), human_rate(module.data.write_rate)));
+
+    details.join(" ")
+}
+
+/// Create a lemonbar-formatted `String` representing system pressure.
+///
+/// The icon changes color when cpu, memory or io is under pressure.
+/// In detailed mode the load average and the stall percentage of
+/// each resource is shown.
+pub fn pressure(module: &mut Module<()>) -> String {
+    let root = Path::new(ROOT);
+
+    // Kernels without PSI don't have /proc/pressure
+    let stalls: Vec<(&str, f64)> = [("cpu", "cpu"), ("mem", "memory"), ("io", "io")]
+        .iter()
+        .filter_map(|(name, file)| {
+            let pressure = read_to_string(root.join("proc/pressure").join(file)).ok()?;
+            Some((*name, parse_pressure(&pressure)?))
+        })
+        .collect();
+
+    let worst = stalls.iter().map(|(_, stall)| *stall).fold(0.0, f64::max);
+    let ret = paint(PSI_IND, range_color(&PSI_RANGES, worst as u32), "F");
+
+    if !module.is_detailed() {
+        return ret;
+    }
+
+    let mut details = vec![ret];
+
+    if let Ok(loadavg) = read_to_string(root.join("proc/loadavg")) {
+        let load: Vec<&str> = loadavg.split_whitespace().take(3).collect();
+        details.push(load.join(" "));
+    }
+
+    for (name, stall) in stalls {
+        let text = format!("{} {:.0}%", name, stall);
+        details.push(paint(&text, range_color(&PSI_RANGES, stall as u32), "F"));
+    }
+
+    details.join(" ")
+}
+
+/// Create a lemonbar-formatted `String` representing the audio volume.
+///
+/// The module takes the messages `up`, `down` and `mute`, which are sent
+/// by scrolling and clicking it. The volume is only read again when the
+/// mixer reports a change.
+///
+/// ### Stored data
+/// * The mixer and its last known volume, see `VolumeData`
+///
+/// # Examples
+///
+/// ```
+/// use ba::{volume, BarStr, Mixer, Module, Volume, VolumeData};
+/// use std::io;
+/// use std::sync::{Arc, Mutex};
+/// use std::thread::{self, JoinHandle};
+///
+/// struct FakeMixer(Arc<Mutex<Volume>>);
+///
+/// impl Mixer for FakeMixer {
+///     fn volume(&mut self) -> io::Result<Volume> {
+///         Ok(*self.0.lock().unwrap())
+///     }
+///
+///     fn change_volume(&mut self, percent: i32) -> io::Result<()> {
+///         let mut volume = self.0.lock().unwrap();
+///         volume.percent = (volume.percent as i32 + percent).max(0) as u32;
+///         Ok(())
+///     }
+///
+///     fn toggle_mute(&mut self) -> io::Result<()> {
+///         let mut volume = self.0.lock().unwrap();
+///         volume.muted = !volume.muted;
+///         Ok(())
+///     }
+///
+///     fn watch(&mut self, _changed: Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>> {
+///         Ok(thread::spawn(|| loop { thread::park() }))
+///     }
+/// }
+///
+/// let state = Arc::new(Mutex::new(Volume { percent: 50, muted: false }));
+/// let mut module = Module::new(volume).with_messages();
+/// module.data = VolumeData::with_mixer(Box::new(FakeMixer(state.clone())));
+/// module.set_detailed(true);
+///
+/// assert!(module.create_string().ends_with(" 50%"));
+///
+/// module.send("up");
+/// assert!(module.create_string().ends_with(" 55%"));
+///
+/// module.send("mute");
+/// module.create_string();
+/// assert!(state.lock().unwrap().muted);
+/// ```
+pub fn volume(module: &mut Module<VolumeData>) -> String {
+    let messages = module.messages();
+    let data = &mut module.data;
+    let mixer = data.mixer.get_or_insert_with(|| VOL_BACKEND.mixer());
+
+    for message in messages {
+        let result = match message.as_str() {
+            "up" => mixer.change_volume(VOL_STEP),
+            "down" => mixer.change_volume(-VOL_STEP),
+            "mute" => mixer.toggle_mute(),
+            _ => {
+                warn!("Unknown volume message '{}'", message);
+                continue;
+            }
+        };
+
+        match result {
+            Ok(()) => data.changed.store(true, Ordering::SeqCst),
+            Err(e) => warn!("Failed to change the volume: {}", e),
+        }
+    }
+
+    // Watch for changes, and start watching again if the watcher stopped.
+    // If the mixer can't be watched, read the volume on every update.
+    let watching = data.watcher.as_ref().is_some_and(|w| !w.is_finished());
+    if !watching && !data.unwatchable {
+        let changed = data.changed.clone();
+        let watcher = mixer.watch(Box::new(move || {
+            changed.store(true, Ordering::SeqCst);
+            request_redraw();
+        }));
+
+        match watcher {
+            Ok(watcher) => data.watcher = Some(watcher),
+            Err(e) => {
+                warn!("Failed to watch the mixer, polling instead: {}", e);
+                data.unwatchable = true;
+            }
+        }
+        data.changed.store(true, Ordering::SeqCst);
+    }
+
+    if data.changed.swap(false, Ordering::SeqCst) || data.unwatchable {
+        data.volume = match mixer.volume() {
+            Ok(volume) => Some(volume),
+            Err(e) => {
+                debug!("Failed to read the volume: {}", e);
+                None
+            }
+        };
+    }
+
+    let volume = match data.volume {
+        Some(volume) => volume,
+        None => return String::new(),
+    };
+
+    let ret = if volume.muted {
+        paint(VOL_MUTED_IND, VOL_MUTED_COLOR, "F")
+    } else {
+        String::from(VOL_IND)
+    };
+
+    if module.is_detailed() {
+        format!("{} {}%", ret, volume.percent)
+    } else {
+        ret
+    }
+}
+
+/// Create a lemonbar-formatted `String` representing the screen brightness.
+///
+/// The module takes the messages `up` and `down`, which are sent by
+/// scrolling it, and goes no lower than `BL_MIN`. It is hidden on machines
+/// without a backlight.
+///
+/// ### Stored data
+/// * The watcher telling the module when the brightness changes
+pub fn backlight(module: &mut Module<WatchData>) -> String {
+    let root = Path::new(ROOT).join("sys/class/backlight");
+    let light = match read_backlight(ROOT, BL_DEVICE) {
+        Some(light) => light,
+        None => return String::new(),
+    };
+
+    let mut percent = light.percent();
+
+    for message in module.messages() {
+        let step = match message.as_str() {
+            "up" => BL_STEP,
+            "down" => -BL_STEP,
+            _ => {
+                warn!("Unknown backlight message '{}'", message);
+                continue;
+            }
+        };
+
+        let min = BL_MIN.min(percent as i32);
+        percent = (percent as i32 + step).clamp(min, 100) as u32;
+        set_brightness(&light, percent);
+    }
+
+    module.data.watch(|changed| {
+        let device = root.join(&light.name);
+        inotify::watch(
+            &[device.join("brightness"), device.join("actual_brightness")],
+            libc::IN_MODIFY,
+            changed,
+        )
+    });
+
+    if module.is_detailed() {
+        format!("{} {}%", BL_IND, percent)
+    } else {
+        String::from(BL_IND)
+    }
+}
+
+/// Create a lemonbar-formatted `String` representing the keyboard layout.
+///
+/// Caps Lock and Num Lock are shown when they are on. The module takes
+/// the message `next`, which switches to the next layout when clicked.
+/// In detailed mode all layouts are shown.
+///
+/// ### Stored data
+/// * The keyboard state, followed in the background, see `KeyboardData`
+pub fn keyboard(module: &mut Module<KeyboardData>) -> String {
+    let shared = module.data.state.clone();
+    module.data.watcher.watch(move |changed| follow_keyboard(shared, changed));
+
+    // Sway doesn't tell when the lock keys change, but their leds do
+    if std::env::var_os("SWAYSOCK").is_some() {
+        module.data.leds.watch(|changed| {
+            let leds = lock_led_files(ROOT);
+            if leds.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no lock key leds to watch"));
+            }
+            inotify::watch(&leds, libc::IN_MODIFY, changed)
+        });
+
+        if module.data.leds.changed() {
+            if let Some(state) = module.data.state.lock().unwrap().as_mut() {
+                (state.caps_lock, state.num_lock) = read_lock_leds(ROOT);
+            }
+        }
+    }
+
+    let state = match module.data.state.lock().unwrap().clone() {
+        Some(state) => state,
+        None => return String::new(),
+    };
+
+    for message in module.messages() {
+        match message.as_str() {
+            "next" if !state.layouts.is_empty() => {
+                if let Err(e) = switch_layout(&state) {
+                    warn!("Failed to switch keyboard layout: {}", e);
+                }
+            }
+            "next" => {}
+            _ => warn!("Unknown keyboard message '{}'", message),
+        }
+    }
+
+    let layout_name = |layout: &str| {
+        let short = KB_NAMES.iter().find(|(name, _)| *name == layout).map_or(layout, |(_, short)| short);
+        short.to_uppercase()
+    };
+
+    let mut ret = vec![String::from(KB_IND)];
+
+    if module.is_detailed() {
+        for (i, layout) in state.layouts.iter().enumerate() {
+            if i == state.active {
+                ret.push(layout_name(layout));
+            } else {
+                ret.push(paint(&layout_name(layout), KB_OTHER_LAYOUT_COLOR, "F"));
+            }
+        }
+    } else if let Some(layout) = state.layouts.get(state.active) {
+        ret.push(layout_name(layout));
+    }
+
+    if state.caps_lock {
+        ret.push(paint("CAPS", KB_LOCK_COLOR, "F"));
+    }
+    if state.num_lock {
+        ret.push(paint("NUM", KB_LOCK_COLOR, "F"));
+    }
+
+    ret.join(" ")
+}
+
+/// Create a lemonbar-formatted `String` representing a timer.
+///
+/// The timer counts down, or up as a stopwatch, see `TimerMode`. It takes
+/// the messages `start`, `pause`, `toggle` and `reset`, as well as
+/// `start <duration>` for a countdown, `stopwatch` and `pomodoro`.
+/// When it runs out it turns `TM_EXPIRED_COLOR`, and the next `start`
+/// begins the next pomodoro phase.
+///
+/// The state of the timer is saved in `$XDG_STATE_HOME/ba/timer`,
+/// so it keeps running across restarts.
+///
+/// ### Stored data
+/// * The timer and the clock it runs on, see `TimerData`
+///
+/// # Examples
+///
+/// ```
+/// use ba::{timer, BarStr, Clock, Module, TimerData};
+/// use std::sync::{Arc, Mutex};
+/// use std::time::{Duration, Instant, SystemTime};
+///
+/// struct FakeClock(Instant, SystemTime, Arc<Mutex<Duration>>);
+///
+/// impl Clock for FakeClock {
+///     fn now(&self) -> Instant {
+///         self.0 + *self.2.lock().unwrap()
+///     }
+///
+///     fn system_now(&self) -> SystemTime {
+///         self.1 + *self.2.lock().unwrap()
+///     }
+/// }
+///
+/// let passed = Arc::new(Mutex::new(Duration::from_secs(0)));
+/// let clock = FakeClock(Instant::now(), SystemTime::now(), passed.clone());
+/// let mut module = Module::new(timer).with_messages();
+/// module.data = TimerData::new(Box::new(clock), None);
+/// let mut wait = |seconds| *passed.lock().unwrap() += Duration::from_secs(seconds);
+///
+/// module.send("start 10m");
+/// assert!(module.create_string().contains("10:00"));
+///
+/// wait(90);
+/// assert!(module.create_string().contains("08:30"));
+///
+/// // Messages are handled the next time the module runs
+/// module.send("pause");
+/// module.create_string();
+/// wait(60);
+/// assert!(module.create_string().contains("08:30"));
+///
+/// module.send("start");
+/// module.create_string();
+/// wait(600);
+/// assert!(module.create_string().contains("00:00"));
+/// assert!(module.data.expired());
+///
+/// module.send("stopwatch");
+/// module.create_string();
+/// wait(75);
+/// assert!(module.create_string().contains("01:15"));
+/// ```
+pub fn timer(module: &mut Module<TimerData>) -> String {
+    let messages = module.messages();
+    let detailed = module.is_detailed();
+    let data = &mut module.data;
+
+    if !data.loaded {
+        data.loaded = true;
+        data.load();
+    }
+
+    for message in messages {
+        if let Err(e) = data.handle(&message) {
+            warn!("Rejected timer message '{}': {}", message, e);
+        }
+    }
+
+    let now = data.clock.now();
+    let elapsed = data.elapsed(now);
+
+    let shown = match data.mode.length(data.phase) {
+        Some(length) => {
+            if elapsed >= length && !data.expired {
+                data.expire();
+            }
+            length.saturating_sub(elapsed)
+        }
+        None => elapsed,
+    };
+    data.tick(elapsed);
+
+    // Untouched timers only show the icon, ready to be clicked
+    if data.running_since.is_none() && elapsed.is_zero() && !data.expired {
+        return String::from(TM_IND);
+    }
+
+    let mut text = format_clock(shown);
+    if detailed {
+        if let Some(phase) = data.mode.phase_name(data.phase) {
+            text = format!("{} {}", phase, text);
+        }
+    }
+
+    let color = if data.expired {
+        TM_EXPIRED_COLOR
+    } else if data.running_since.is_none() {
+        TM_PAUSED_COLOR
+    } else {
+        TEXT_COLOR
+    };
+
+    format!("{} {}", TM_IND, paint(&text, color, "F"))
+}
+
+/// Create a lemonbar-formatted `String` representing unread mail.
+///
+/// Messages are counted in the Maildirs in `MAIL_DIRS`, which are watched
+/// for new mail, and by the commands in `MAIL_COMMANDS`, which run when
+/// the Maildirs change and every `MAIL_COMMAND_INTERVAL` seconds. In
+/// detailed mode the count of each is shown. The message `open` starts `MAIL_CLIENT`.
+///
+/// ### Stored data
+/// * The counts, see `MailData`
+pub fn mail(module: &mut Module<MailData>) -> String {
+    for message in module.messages() {
+        match message.as_str() {
+            "open" => run_command(MAIL_CLIENT),
+            _ => warn!("Unknown mail message '{}'", message),
+        }
+    }
+
+    let dirs: Vec<PathBuf> = MAIL_DIRS.iter().map(|dir| expand_home(dir)).collect();
+    let data = &mut module.data;
+
+    data.watcher.watch(|changed| {
+        let (new, missing): (Vec<PathBuf>, Vec<PathBuf>) =
+            dirs.iter().map(|dir| dir.join("new")).partition(|new| new.is_dir());
+
+        // Watching nothing would never report a change, so poll instead
+        if new.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no Maildir to watch for mail"));
+        }
+        for new in missing {
+            warn!("Not watching {} for mail, it is not a directory", new.display());
+        }
+
+        inotify::watch(
+            &new,
+            libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO,
+            changed,
+        )
+    });
+
+    let watching = data.watcher.is_watching();
+    if data.watcher.changed() {
+        data.counts = dirs
+            .iter()
+            .map(|dir| {
+                let name = dir.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+                (name, count_maildir(dir))
+            })
+            .collect();
+
+        // The commands usually count the same mail, so they run when it changes
+        data.commands_stale |= watching;
+    }
+
+    // They may be slow, so they run in the background, one run at a time
+    let due = data
+        .commands_started
+        .is_none_or(|started| started.elapsed() >= Duration::from_secs(MAIL_COMMAND_INTERVAL));
+    if !MAIL_COMMANDS.is_empty() && (data.commands_stale || due) && !data.counting.swap(true, Ordering::SeqCst) {
+        data.commands_stale = false;
+        data.commands_started = Some(Instant::now());
+        let counts = data.command_counts.clone();
+        let counting = data.counting.clone();
+
+        thread::spawn(move || {
+            let counted = MAIL_COMMANDS.iter().map(|command| (String::from(*command), count_command(command))).collect();
+            *counts.lock().unwrap() = counted;
+            counting.store(false, Ordering::SeqCst);
+            request_redraw();
+        });
+    }
+
+    let command_counts = module.data.command_counts.lock().unwrap().clone();
+    let counts: Vec<&(String, usize)> = module.data.counts.iter().chain(&command_counts).collect();
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+
+    if total == 0 {
+        return paint(MAIL_IND, MAIL_NONE_COLOR, "F");
+    }
+
+    let mut ret = vec![paint(&format!("{} {}", MAIL_IND, total), MAIL_UNREAD_COLOR, "F")];
+
+    if module.is_detailed() && counts.len() > 1 {
+        ret.extend(counts.iter().map(|(name, count)| format!("{} {}", name, count)));
+    }
+
+    ret.join(" ")
+}
+
+/// Create a lemonbar-formatted `String` representing desktop notifications.
+///
+/// Shows how many notifications are waiting, and whether do-not-disturb
+/// is on. The message `dnd` toggles do-not-disturb. In detailed mode the
+/// summary of the latest notification is shown. Nothing is shown if the
+/// notification daemon can't be reached.
+///
+/// ### Stored data
+/// * The D-Bus session connection, made when first needed
+pub fn notifications(module: &mut Module<Option<Connection>>) -> String {
+    let messages = module.messages();
+
+    if module.data.is_none() {
+        match Connection::session() {
+            Ok(connection) => module.data = Some(connection),
+            Err(e) => {
+                debug!("Failed to connect to the session bus: {}", e);
+                return String::new();
+            }
+        }
+    }
+    let connection = module.data.as_ref().unwrap();
+
+    let mut status = match NOTIF_DAEMON.status(connection) {
+        Ok(status) => status,
+        Err(e) => {
+            debug!("Failed to reach the notification daemon: {}", e);
+            return String::new();
+        }
+    };
+
+    for message in messages {
+        match message.as_str() {
+            "dnd" => match NOTIF_DAEMON.set_paused(connection, !status.paused) {
+                Ok(()) => status.paused = !status.paused,
+                Err(e) => warn!("Failed to toggle do-not-disturb: {}", e),
+            },
+            _ => warn!("Unknown notifications message '{}'", message),
+        }
+    }
+
+    let mut ret = if status.paused {
+        paint(NOTIF_PAUSED_IND, NOTIF_PAUSED_COLOR, "F")
+    } else {
+        String::from(NOTIF_IND)
+    };
+
+    if status.count > 0 {
+        ret = format!("{} {}", ret, status.count);
+    }
+
+    if let (true, Some(latest)) = (module.is_detailed(), status.latest) {
+        // Anyone can send a notification, so it mustn't add buttons to the bar
+        let latest = escape(&latest);
+        let mut summary: String = latest.chars().take(NOTIF_SUMMARY_LENGTH).collect();
+        if summary.len() < latest.len() {
+            summary.push('…');
+        }
+        ret = format!("{} {}", ret, summary);
+    }
+
+    ret
+}
+
+/// Create a lemonbar-formatted `String` representing failed systemd units.
+///
+/// Counts the failed units of the system and user managers, and the units
+/// in `SD_UNITS` and `SD_USER_UNITS` that are not active. In detailed mode
+/// their names are shown. Nothing is shown when all is well.
+///
+/// ### Stored data
+/// * A connection to each manager and the units with problems
+pub fn systemd(module: &mut Module<SystemdData>) -> String {
+    let data = &mut module.data;
+    data.system.update(Connection::system, &SD_UNITS);
+    data.user.update(Connection::session, &SD_USER_UNITS);
+
+    let data = &module.data;
+    let problems: Vec<&String> = data.system.problems.iter().chain(&data.user.problems).collect();
+
+    if problems.is_empty() {
+        return String::new();
+    }
+
+    let mut ret = vec![paint(&format!("{} {}", SD_IND, problems.len()), SD_FAILED_COLOR, "F")];
+
+    if module.is_detailed() {
+        ret.extend(problems.into_iter().cloned());
+    }
+
+    ret.join(" ")
+}
+
+/*                  */
+/* Helper Functions */
+/*                  */
+
+/// Something telling the time, so that timers can be tested.
+pub trait Clock: Send {
+    /// The monotonic time, which timers run on.
+    fn now(&self) -> Instant;
+
+    /// The wall clock time, which is saved so that timers survive restarts.
+    fn system_now(&self) -> SystemTime;
+}
+
+/// The clocks of the system.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// What a timer counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Count down from a duration
+    Countdown(Duration),
+    /// Count up from zero
+    Stopwatch,
+    /// Count down work and breaks, `TM_WORK` and `TM_BREAK` minutes long,
+    /// with a long break after every `TM_LONG_BREAK_EVERY` pomodoros
+    Pomodoro,
+}
+
+impl TimerMode {
+    /// How long phase `phase` is, or `None` for stopwatches.
+    fn length(self, phase: u64) -> Option<Duration> {
+        let minutes = match self {
+            TimerMode::Countdown(length) => return Some(length),
+            TimerMode::Stopwatch => return None,
+            TimerMode::Pomodoro if phase.is_multiple_of(2) => TM_WORK,
+            TimerMode::Pomodoro if (phase / 2 + 1).is_multiple_of(TM_LONG_BREAK_EVERY) => TM_LONG_BREAK,
+            TimerMode::Pomodoro => TM_BREAK,
+        };
+
+        Some(Duration::from_secs(minutes * 60))
+    }
+
+    /// What phase `phase` is called, such as `work 2/4`.
+    fn phase_name(self, phase: u64) -> Option<String> {
+        match (self, self.length(phase)) {
+            (TimerMode::Pomodoro, _) if phase.is_multiple_of(2) => {
+                Some(format!("work {}/{}", phase / 2 % TM_LONG_BREAK_EVERY + 1, TM_LONG_BREAK_EVERY))
+            }
+            (TimerMode::Pomodoro, Some(length)) if length.as_secs() == TM_LONG_BREAK * 60 => {
+                Some(String::from("long break"))
+            }
+            (TimerMode::Pomodoro, _) => Some(String::from("break")),
+            _ => None,
+        }
+    }
+}
+
+/// Data stored by the timer module.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{timer, BarStr, Clock, Module, TimerData};
+/// use std::path::Path;
+/// use std::sync::{Arc, Mutex};
+/// use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+///
+/// struct FakeClock(Instant, SystemTime, Arc<Mutex<Duration>>);
+///
+/// impl Clock for FakeClock {
+///     fn now(&self) -> Instant {
+///         self.0 + *self.2.lock().unwrap()
+///     }
+///
+///     fn system_now(&self) -> SystemTime {
+///         self.1 + *self.2.lock().unwrap()
+///     }
+/// }
+///
+/// let passed = Arc::new(Mutex::new(Duration::from_secs(0)));
+/// // The state is saved in milliseconds
+/// let started = UNIX_EPOCH + Duration::from_secs(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+/// let path = std::env::temp_dir().join(format!("ba-timer-{}", std::process::id()));
+/// let bar = |passed: &Arc<Mutex<Duration>>| {
+///     let clock = FakeClock(Instant::now(), started, passed.clone());
+///     let mut module = Module::new(timer).with_messages();
+///     module.data = TimerData::new(Box::new(clock), Some(path.clone()));
+///     module
+/// };
+/// let wait = |seconds| *passed.lock().unwrap() += Duration::from_secs(seconds);
+///
+/// let mut module = bar(&passed);
+/// module.send("start 10m");
+/// module.create_string();
+/// wait(60);
+/// drop(module);
+///
+/// // The timer keeps running while the bar is down
+/// wait(120);
+/// let mut module = bar(&passed);
+/// assert!(module.create_string().contains("07:00"));
+///
+/// // And stays paused
+/// module.send("pause");
+/// module.create_string();
+/// drop(module);
+/// wait(300);
+/// assert!(bar(&passed).create_string().contains("07:00"));
+/// std::fs::remove_file(&path).unwrap();
+///
+/// // A countdown of 25 minutes with one minute counted before it was
+/// // started again at 2026-01-01 00:00 UTC, five minutes ago
+/// let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/timer/timer");
+/// let now = UNIX_EPOCH + Duration::from_secs(1767225600 + 300);
+/// let clock = FakeClock(Instant::now(), now, Arc::new(Mutex::new(Duration::ZERO)));
+/// let mut module = Module::new(timer);
+/// module.data = TimerData::new(Box::new(clock), Some(fixture));
+/// assert!(module.create_string().contains("19:00"));
+/// ```
+pub struct TimerData {
+    mode: TimerMode,
+    /// Pomodoro phase, even for work and odd for breaks
+    phase: u64,
+    /// Time counted before the timer was last started
+    counted: Duration,
+    running_since: Option<Instant>,
+    expired: bool,
+    clock: Box<dyn Clock>,
+    path: Option<PathBuf>,
+    loaded: bool,
+    /// Redraws the bar every second while running, until told to stop.
+    /// Started for the `running_since` it is stored with
+    ticker: Option<(Instant, Arc<AtomicBool>)>,
+}
+
+impl Default for TimerData {
+    fn default() -> TimerData {
+        TimerData::new(Box::new(SystemClock), Some(state_dir().join("timer")))
+    }
+}
+
+impl TimerData {
+    /// Create a timer running on `clock`, saving its state to `path`.
+    pub fn new(clock: Box<dyn Clock>, path: Option<PathBuf>) -> TimerData {
+        TimerData {
+            mode: TM_MODE,
+            phase: 0,
+            counted: Duration::ZERO,
+            running_since: None,
+            expired: false,
+            clock,
+            path,
+            loaded: false,
+            ticker: None,
+        }
+    }
+
+    /// Whether the timer has run out.
+    pub fn expired(&self) -> bool {
+        self.expired
+    }
+
+    /// Redraw the bar each time another second has been counted while
+    /// running, since the bar is otherwise only redrawn every few seconds.
+    fn tick(&mut self, elapsed: Duration) {
+        if self.ticker.as_ref().map(|(since, _)| *since) == self.running_since {
+            return;
+        }
+
+        if let Some((_, ticking)) = self.ticker.take() {
+            ticking.store(false, Ordering::SeqCst);
+        }
+
+        if let Some(since) = self.running_since {
+            let ticking = Arc::new(AtomicBool::new(true));
+            let still_ticking = ticking.clone();
+            let mut next = Instant::now() + (Duration::from_secs(1) - Duration::from_nanos(elapsed.subsec_nanos().into()));
+
+            thread::spawn(move || loop {
+                thread::sleep(next.saturating_duration_since(Instant::now()));
+                if !still_ticking.load(Ordering::SeqCst) {
+                    break;
+                }
+                next += Duration::from_secs(1);
+                request_redraw();
+            });
+
+            self.ticker = Some((since, ticking));
+        }
+    }
+
+    /// The time counted so far.
+    fn elapsed(&self, now: Instant) -> Duration {
+        self.counted + self.running_since.map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+    }
+
+    /// Handle a message such as `start` or `start 25m`.
+    fn handle(&mut self, message: &str) -> Result<(), String> {
+        let now = self.clock.now();
+        let mut words = message.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("start"), Some(length)) => {
+                let length = parse_timer_duration(length).ok_or("invalid duration")?;
+                self.restart(TimerMode::Countdown(length), now);
+            }
+            (Some("stopwatch"), None) => self.restart(TimerMode::Stopwatch, now),
+            (Some("pomodoro"), None) => self.restart(TimerMode::Pomodoro, now),
+            (Some("start"), None) => self.start(now),
+            (Some("pause"), None) => self.pause(now),
+            (Some("toggle"), None) if self.running_since.is_some() => self.pause(now),
+            (Some("toggle"), None) => self.start(now),
+            (Some("reset"), None) => {
+                self.phase = 0;
+                self.counted = Duration::ZERO;
+                self.running_since = None;
+                self.expired = false;
+            }
+            _ => return Err(String::from("unknown message")),
+        }
+
+        self.save();
+        Ok(())
+    }
+
+    fn start(&mut self, now: Instant) {
+        // Starting a timer that has run out starts the next round
+        if self.expired {
+            if self.mode == TimerMode::Pomodoro {
+                self.phase += 1;
+            }
+            self.counted = Duration::ZERO;
+            self.running_since = None;
+            self.expired = false;
+        }
+
+        if self.running_since.is_none() {
+            self.running_since = Some(now);
+        }
+    }
+
+    fn pause(&mut self, now: Instant) {
+        self.counted = self.elapsed(now);
+        self.running_since = None;
+    }
+
+    fn restart(&mut self, mode: TimerMode, now: Instant) {
+        self.mode = mode;
+        self.phase = 0;
+        self.counted = Duration::ZERO;
+        self.running_since = Some(now);
+        self.expired = false;
+    }
+
+    /// Stop the timer when it runs out, and tell about it.
+    fn expire(&mut self) {
+        let now = self.clock.now();
+        self.pause(now);
+        self.expired = true;
+        self.save();
+
+        let summary = match self.mode.phase_name(self.phase) {
+            Some(phase) => format!("{} is over", phase),
+            None => String::from("Time is up"),
+        };
+        notify(&summary, "", false);
+
+        if let Some(command) = TM_COMMAND {
+            run_command(command);
+        }
+    }
+
+    /// Save the state of the timer. The time it started is saved as
+    /// wall clock time, since monotonic time doesn't survive restarts.
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mode = match self.mode {
+            TimerMode::Countdown(length) => format!("countdown {}", length.as_millis()),
+            TimerMode::Stopwatch => String::from("stopwatch"),
+            TimerMode::Pomodoro => String::from("pomodoro"),
+        };
+        let started = self.running_since.map(|since| {
+            let running = self.clock.now().saturating_duration_since(since);
+            let started = self.clock.system_now() - running;
+            started.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+        });
+
+        let state = format!(
+            "mode {}\nphase {}\ncounted {}\nstarted {}\nexpired {}\n",
+            mode,
+            self.phase,
+            self.counted.as_millis(),
+            started.map_or(String::from("-"), |started| started.to_string()),
+            self.expired,
+        );
+
+        let saved = path
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .and_then(|_| std::fs::write(path, state));
+
+        if let Err(e) = saved {
+            warn!("Failed to save the timer to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Load the state saved by `save`, if there is one.
+    fn load(&mut self) {
+        let state = match self.path.as_ref().and_then(|path| read_to_string(path).ok()) {
+            Some(state) => state,
+            None => return,
+        };
+
+        let fields: HashMap<&str, &str> = state.lines().filter_map(|line| line.split_once(' ')).collect();
+        let millis = |value: &str| value.parse().ok().map(Duration::from_millis);
+
+        self.mode = match fields.get("mode").map(|mode| mode.split_once(' ').unwrap_or((mode, ""))) {
+            Some(("countdown", length)) => match millis(length) {
+                Some(length) => TimerMode::Countdown(length),
+                None => return,
+            },
+            Some(("stopwatch", _)) => TimerMode::Stopwatch,
+            Some(("pomodoro", _)) => TimerMode::Pomodoro,
+            _ => return,
+        };
+        self.phase = fields.get("phase").and_then(|phase| phase.parse().ok()).unwrap_or(0);
+        self.counted = fields.get("counted").and_then(|counted| millis(counted)).unwrap_or_default();
+        self.expired = fields.get("expired") == Some(&"true");
+
+        // Time spent while the bar was not running counts too
+        self.running_since = fields.get("started").and_then(|started| millis(started)).map(|started| {
+            let running = self
+                .clock
+                .system_now()
+                .duration_since(UNIX_EPOCH + started)
+                .unwrap_or_default();
+            let now = self.clock.now();
+            now.checked_sub(running).unwrap_or(now)
+        });
+    }
+}
+
+/// Parse a timer duration such as `25m`, `90s` or `1h30m`.
+/// Plain numbers are minutes.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_timer_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(parse_timer_duration("25"), Some(Duration::from_secs(25 * 60)));
+/// assert_eq!(parse_timer_duration("1h30m"), Some(Duration::from_secs(90 * 60)));
+/// assert_eq!(parse_timer_duration("90s"), Some(Duration::from_secs(90)));
+/// assert_eq!(parse_timer_duration("soon"), None);
+/// assert_eq!(parse_timer_duration("999999999999999999"), None);
+/// assert_eq!(parse_timer_duration("99999999999999999h"), None);
+/// ```
+pub fn parse_timer_duration(duration: &str) -> Option<Duration> {
+    if let Ok(minutes) = duration.parse::<u64>() {
+        return minutes.checked_mul(60).map(Duration::from_secs);
+    }
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let n: u64 = number.parse().ok()?;
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        seconds = n.checked_mul(unit).and_then(|n| seconds.checked_add(n))?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+/// Format a duration as `MM:SS`, or `H:MM:SS` from an hour and up.
+fn format_clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Directory where state that should survive restarts is kept,
+/// `$XDG_STATE_HOME/ba`.
+pub fn state_dir() -> PathBuf {
+    let state = std::env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".local/state"));
+    state.join("ba")
+}
+
+/// Create the text shown by `ba calendar`: a calendar of the current month
+/// and today's events from the calendars in `CAL_PATH`.
+pub fn calendar() -> String {
+    render_calendar(ROOT, Local::now().date_naive(), &ical::read_calendars(&expand_home(CAL_PATH)))
+}
+
+/// Create a month calendar with `today` highlighted, followed by the events
+/// of `today` in local time, with the time zones in `<root>`. Weeks start on
+/// Monday, and names follow the `LC_TIME` locale.
+///
+/// # Examples
+///
+/// ```
+/// use ba::ical::parse_ics;
+/// use ba::{render_calendar, set_time_locale};
+/// use chrono::NaiveDate;
+///
+/// set_time_locale("C");
+/// let events = parse_ics("\
+/// BEGIN:VEVENT
+/// DTSTART:20261019T091500
+/// SUMMARY:Standup
+/// END:VEVENT
+/// ");
+/// let today = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+/// let calendar = render_calendar("/", today, &events);
+/// let lines: Vec<&str> = calendar.lines().collect();
+///
+/// assert_eq!(lines[1], "Mo Tu We Th Fr Sa Su");
+/// assert_eq!(lines[2], "          1  2  3  4");
+/// assert_eq!(lines[5], "\x1b[7m19\x1b[0m 20 21 22 23 24 25");
+/// assert_eq!(lines[lines.len() - 1], "09:15    Standup");
+/// ```
+pub fn render_calendar(root: &str, today: NaiveDate, events: &[ical::Event]) -> String {
+    let name = |date: NaiveDate, format: &str| {
+        let noon = date.and_hms_opt(12, 0, 0).unwrap_or_default().and_utc().timestamp();
+        format_time(format, noon, Some((0, "UTC")))
+    };
+
+    let first = today.with_day(1).unwrap_or(today);
+    let monday = first - chrono::Duration::days(i64::from(first.weekday().num_days_from_monday()));
+
+    let weekdays: Vec<String> = (0..7)
+        .map(|day| name(monday + chrono::Duration::days(day), "%a").chars().take(2).collect())
+        .collect();
+
+    let mut lines = vec![
+        format!("{:^20}", name(today, "%B %Y")).trim_end().to_string(),
+        weekdays.join(" "),
+    ];
+
+    let mut date = monday;
+    while date.month() == first.month() || date < first {
+        let week: Vec<String> = (0..7)
+            .map(|day| {
+                let day = date + chrono::Duration::days(day);
+                if day.month() != first.month() {
+                    String::from("  ")
+                } else if day == today {
+                    format!("\x1b[7m{:>2}\x1b[0m", day.day())
+                } else {
+                    format!("{:>2}", day.day())
+                }
+            })
+            .collect();
+
+        lines.push(week.join(" ").trim_end().to_string());
+        date += chrono::Duration::days(7);
+    }
+
+    lines.push(String::new());
+
+    let agenda = ical::events_on(root, events, today);
+    if agenda.is_empty() {
+        lines.push(String::from("No events today"));
+    }
+
+    for occurrence in agenda {
+        let start = if occurrence.all_day {
+            String::from("all day")
+        } else {
+            strftime(TI_FORMAT, occurrence.start.and_utc().timestamp(), Some((0, "")), ":")
+        };
+        lines.push(format!("{:<8} {}", start, occurrence.summary));
+    }
+
+    lines.join("\n")
+}
+
+/// Use the weekday and month names, and the formats of `%c`, `%x` and `%X`,
+/// of the `LC_TIME` locale `name`, or of the environment if it is empty.
+pub fn set_time_locale(name: &str) {
+    match CString::new(name) {
+        Ok(name) => unsafe {
+            libc::setlocale(libc::LC_TIME, name.as_ptr());
+        },
+        Err(_) => warn!("Invalid locale name {}", name),
+    }
+}
+
+/// Format a Unix time like `strftime`, in local time or in a time zone given
+/// by its offset from UTC in seconds and its abbreviation.
+/// `%:` is replaced by a colon colored with `TI_COLON_COLOR`.
+///
+/// Names of weekdays and months follow the locale set by `set_time_locale`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{format_time, set_time_locale};
+///
+/// set_time_locale("C");
+/// let cet = Some((3600, "CET"));
+///
+/// assert_eq!(format_time("%H:%M %Z", 0, cet), "01:00 CET");
+/// assert_eq!(format_time("%Y-%m-%d %-I:%M %p %A", 0, cet), "1970-01-01 1:00 AM Thursday");
+/// assert_eq!(format_time("%c %:z", 0, cet), "Thu Jan  1 01:00:00 1970 +01:00");
+/// assert_eq!(format_time("100%% %%:", 0, cet), "100% %:");
+/// assert!(format_time("%H%:%M", 0, cet).starts_with("01%{F"));
+/// ```
+pub fn format_time(format: &str, time: i64, zone: Option<(i32, &str)>) -> String {
+    strftime(format, time, zone, &paint(":", TI_COLON_COLOR, "F"))
+}
+
+/// Format a Unix time like `format_time`, with `colon` for `%:`.
+fn strftime(format: &str, time: i64, zone: Option<(i32, &str)>, colon: &str) -> String {
+    let (offset, abbreviation) = match zone {
+        Some((offset, abbreviation)) => (offset, String::from(abbreviation)),
+        None => local_zone(ROOT, time),
+    };
+
+    let offset = FixedOffset::east_opt(offset).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    let time = match DateTime::from_timestamp(time, 0) {
+        Some(time) => time.with_timezone(&offset),
+        None => return String::new(),
+    };
+
+    let mut formatted = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+
+        // Padding flags are left to chrono
+        let mut spec = String::from("%");
+        while let Some(flag) = chars.next_if(|c| matches!(c, '-' | '_' | '0')) {
+            spec.push(flag);
+        }
+
+        match chars.next() {
+            Some('%') => formatted.push('%'),
+            Some(':') if chars.peek() != Some(&'z') => formatted.push_str(colon),
+            Some('Z') => formatted.push_str(&abbreviation),
+            Some('a') => formatted.push_str(&langinfo(libc::ABDAY_1 + time.weekday().num_days_from_sunday() as i32)),
+            Some('A') => formatted.push_str(&langinfo(libc::DAY_1 + time.weekday().num_days_from_sunday() as i32)),
+            Some('b') | Some('h') => formatted.push_str(&langinfo(libc::ABMON_1 + time.month0() as i32)),
+            Some('B') => formatted.push_str(&langinfo(libc::MON_1 + time.month0() as i32)),
+            Some(c @ 'p') | Some(c @ 'P') => {
+                let meridiem = langinfo(if time.hour() < 12 { libc::AM_STR } else { libc::PM_STR });
+                formatted.push_str(&if c == 'P' { meridiem.to_lowercase() } else { meridiem });
+            }
+            Some(c @ 'c') | Some(c @ 'x') | Some(c @ 'X') | Some(c @ 'r') => {
+                let item = match c {
+                    'c' => libc::D_T_FMT,
+                    'x' => libc::D_FMT,
+                    'X' => libc::T_FMT,
+                    _ => libc::T_FMT_AMPM,
+                };
+                let zone = Some((offset.local_minus_utc(), abbreviation.as_str()));
+                formatted.push_str(&strftime(&langinfo(item), time.timestamp(), zone, colon));
+            }
+            Some(c) => {
+                spec.push(c);
+                if c == ':' {
+                    spec.extend(chars.next());
+                }
+
+                // Unknown specifiers are left out
+                if !StrftimeItems::new(&spec).any(|item| item == Item::Error) {
+                    formatted.push_str(&time.format(&spec).to_string());
+                }
+            }
+            None => {}
+        }
+    }
+
+    formatted
+}
+
+/// Read a string, such as a month name, of the `LC_TIME` locale.
+fn langinfo(item: libc::nl_item) -> String {
+    let value = unsafe { libc::nl_langinfo(item) };
+    if value.is_null() {
+        return String::new();
+    }
+
+    unsafe { CStr::from_ptr(value) }.to_string_lossy().into_owned()
+}
+
+/// Time zones read so far, by the path of their file and when it was modified.
+type ZoneCache = Vec<(PathBuf, Option<SystemTime>, Option<Arc<tzif::TimeZone>>)>;
+static ZONES: Mutex<ZoneCache> = Mutex::new(Vec::new());
+
+/// Read the time zone in the TZif file at `path`, once for as long as the
+/// file is unchanged.
+fn read_zone(path: &Path) -> Option<Arc<tzif::TimeZone>> {
+    let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let mut zones = ZONES.lock().unwrap();
+
+    if let Some((_, _, zone)) = zones.iter().find(|(p, m, _)| p == path && *m == modified) {
+        return zone.clone();
+    }
+
+    let zone = std::fs::read(path).ok().and_then(|data| tzif::TimeZone::parse(&data)).map(Arc::new);
+    zones.retain(|(p, _, _)| p != path);
+    zones.push((path.to_path_buf(), modified, zone.clone()));
+    zone
+}
+
+/// Look up the offset from UTC in seconds and the abbreviation of the local
+/// time zone at a Unix time, from `$TZ` or `<root>/etc/localtime`.
+fn local_zone(root: &str, time: i64) -> (i32, String) {
+    let zone = match std::env::var("TZ") {
+        Ok(tz) if !tz.is_empty() => {
+            let name = tz.trim_start_matches(':');
+            let path = Path::new(root).join("usr/share/zoneinfo").join(name);
+            match read_zone(&path) {
+                Some(zone) => zone.find(time),
+                None => tzif::Rule::parse(&tz).map(|rule| rule.find(time)),
+            }
+        }
+        _ => read_zone(&Path::new(root).join("etc/localtime")).and_then(|zone| zone.find(time)),
+    };
+
+    zone.unwrap_or_else(|| {
+        let offset = Local.timestamp_opt(time, 0).single().map(|time| time.offset().fix()).unwrap_or(Utc.fix());
+        (offset.local_minus_utc(), offset.to_string())
+    })
+}
+
+/// Look up the offset from UTC in seconds and the abbreviation of the IANA
+/// time zone `name` at a Unix time, from `<root>/usr/share/zoneinfo`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::zone_offset;
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/zoneinfo");
+/// let root = root.to_str().unwrap();
+///
+/// assert_eq!(zone_offset(root, "Asia/Tokyo", 0), Some((32400, String::from("JST"))));
+/// // 2026-07-01 and 2026-12-01
+/// assert_eq!(zone_offset(root, "Europe/Stockholm", 1782864000), Some((7200, String::from("CEST"))));
+/// assert_eq!(zone_offset(root, "Europe/Stockholm", 1796083200), Some((3600, String::from("CET"))));
+/// // 2040-07-01 and 2040-12-01, after the last transition in the file
+/// assert_eq!(zone_offset(root, "Europe/Stockholm", 2224713600), Some((7200, String::from("CEST"))));
+/// assert_eq!(zone_offset(root, "Europe/Stockholm", 2237932800), Some((3600, String::from("CET"))));
+/// assert_eq!(zone_offset(root, "Asia/Seoul", 0), None);
+/// ```
+pub fn zone_offset(root: &str, name: &str, time: i64) -> Option<(i32, String)> {
+    read_zone(&Path::new(root).join("usr/share/zoneinfo").join(name))?.find(time)
+}
+
+/// The keyboard layouts and lock keys.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyboardState {
+    pub layouts: Vec<String>,
+    /// Index of the active layout in `layouts`
+    pub active: usize,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+/// Data stored by the keyboard module.
+#[derive(Default)]
+pub struct KeyboardData {
+    state: Arc<Mutex<Option<KeyboardState>>>,
+    watcher: WatchData,
+    /// The lock key leds, under sway
+    leds: WatchData,
+}
+
+/// Keep `state` up to date in the background, over sway IPC if `$SWAYSOCK`
+/// is set and over the X connection otherwise.
+fn follow_keyboard(state: Arc<Mutex<Option<KeyboardState>>>, changed: Box<dyn Fn() + Send>)
+    -> io::Result<JoinHandle<()>>
+{
+    let set = move |new: io::Result<KeyboardState>| {
+        let ok = new.is_ok();
+        *state.lock().unwrap() = new.ok();
+        changed();
+        ok
+    };
+
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let mut events = sway::Ipc::connect()?;
+        let mut requests = sway::Ipc::connect()?;
+        events.request(sway::SUBSCRIBE, r#"["input"]"#)?;
+
+        let mut read = move || {
+            let inputs = requests.request(sway::GET_INPUTS, "")?;
+            let mut state = parse_sway_inputs(&inputs)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no keyboard"))?;
+
+            // Sway doesn't report lock keys, but the keyboard leds show them
+            let (caps_lock, num_lock) = read_lock_leds(ROOT);
+            state.caps_lock = caps_lock;
+            state.num_lock = num_lock;
+            Ok(state)
+        };
+
+        return Ok(thread::spawn(move || {
+            while set(read()) && events.receive().is_ok() {}
+        }));
+    }
+
+    let mut keyboard = xkb::Keyboard::connect()?;
+
+    Ok(thread::spawn(move || {
+        while set(keyboard.state()) && keyboard.wait().is_ok() {}
+    }))
+}
+
+/// Switch to the layout after the active one.
+fn switch_layout(state: &KeyboardState) -> io::Result<()> {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let command = "input type:keyboard xkb_switch_layout next";
+        sway::Ipc::connect()?.request(sway::RUN_COMMAND, command)?;
+        return Ok(());
+    }
+
+    xkb::Keyboard::connect()?.lock_group((state.active + 1) % state.layouts.len())
+}
+
+/// Parse the layouts from the `_XKB_RULES_NAMES` property of the root window,
+/// which holds the rules, model, layouts, variants and options.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_xkb_rules_names;
+///
+/// let names = b"evdev\0pc105\0us,se\0,\0grp:alt_shift_toggle\0";
+/// assert_eq!(parse_xkb_rules_names(names), vec!["us", "se"]);
+/// ```
+pub fn parse_xkb_rules_names(names: &[u8]) -> Vec<String> {
+    names
+        .split(|byte| *byte == 0)
+        .nth(2)
+        .map(|layouts| {
+            String::from_utf8_lossy(layouts)
+                .split(',')
+                .filter(|layout| !layout.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse the layouts of the first keyboard from the reply to a sway
+/// `GET_INPUTS` message. Lock keys are left off.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{parse_sway_inputs, KeyboardState};
+///
+/// let inputs = r#"[
+///     {"identifier": "1:1:Power_Button", "type": "switch"},
+///     {"identifier": "1:1:AT_Translated_Set_2_keyboard", "type": "keyboard",
+///      "xkb_layout_names": ["English (US)", "Swedish"], "xkb_active_layout_index": 1}
+/// ]"#;
+///
+/// assert_eq!(parse_sway_inputs(inputs), Some(KeyboardState {
+///     layouts: vec![String::from("English (US)"), String::from("Swedish")],
+///     active: 1,
+///     ..Default::default()
+/// }));
+/// ```
+pub fn parse_sway_inputs(inputs: &str) -> Option<KeyboardState> {
+    let inputs: Json = serde_json::from_str(inputs).ok()?;
+
+    let keyboard = inputs.as_array()?.iter().find(|input| {
+        input["type"] == "keyboard" && input["xkb_active_layout_index"].is_u64()
+    })?;
+
+    Some(KeyboardState {
+        layouts: keyboard["xkb_layout_names"]
+            .as_array()?
+            .iter()
+            .filter_map(|name| Some(String::from(name.as_str()?)))
+            .collect(),
+        active: keyboard["xkb_active_layout_index"].as_u64()? as usize,
+        ..Default::default()
+    })
+}
+
+/// Read whether the Caps Lock and Num Lock leds are on
+/// in `<root>/sys/class/leds`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::read_lock_leds;
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/keyboard");
+///
+/// // Caps Lock is on and Num Lock is off
+/// assert_eq!(read_lock_leds(root.to_str().unwrap()), (true, false));
+/// ```
+pub fn read_lock_leds(root: &str) -> (bool, bool) {
+    let leds = lock_led_files(root);
+
+    let on = |kind: &str| {
+        leds.iter()
+            .filter(|led| led.parent().is_some_and(|led| led.to_string_lossy().ends_with(kind)))
+            .any(|led| read_to_string(led).is_ok_and(|b| b.trim() != "0"))
+    };
+
+    (on("::capslock"), on("::numlock"))
+}
+
+/// The brightness files of the Caps Lock and Num Lock leds of every keyboard.
+fn lock_led_files(root: &str) -> Vec<PathBuf> {
+    sorted_entries(&Path::new(root).join("sys/class/leds"))
+        .into_iter()
+        .filter(|led| ["::capslock", "::numlock"].iter().any(|kind| led.to_string_lossy().ends_with(kind)))
+        .map(|led| led.join("brightness"))
+        .collect()
+}
+
+/// Notification daemons that can be asked about their notifications.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationDaemon {
+    Dunst,
+    /// Mako, where do-not-disturb is the mode `do-not-disturb`
+    Mako,
+}
+
+/// What a notification daemon is showing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NotificationStatus {
+    /// Notifications shown or waiting to be shown
+    pub count: u32,
+    /// Whether do-not-disturb is on
+    pub paused: bool,
+    /// Summary of the latest notification
+    pub latest: Option<String>,
+}
+
+impl NotificationDaemon {
+    /// Ask the daemon what it is showing over a D-Bus connection.
+    pub fn status(self, connection: &Connection) -> zbus::Result<NotificationStatus> {
+        match self {
+            NotificationDaemon::Dunst => {
+                let get_u32 = |name| -> zbus::Result<u32> {
+                    Ok(u32::try_from(get_property(connection, DUNST, DUNST_PATH, DUNST_INTERFACE, name)?)?)
+                };
+                let paused = get_property(connection, DUNST, DUNST_PATH, DUNST_INTERFACE, "paused")?;
+
+                // Versions that can't list what is shown show no summary,
+                // since the history only has dismissed notifications
+                let latest = match list_notifications(
+                    connection, DUNST, DUNST_PATH, DUNST_INTERFACE, "NotificationListDisplayed",
+                ) {
+                    Ok(shown) => latest_summary(&shown),
+                    Err(e) => {
+                        debug!("Failed to list the notifications shown by dunst: {}", e);
+                        None
+                    }
+                };
+
+                Ok(NotificationStatus {
+                    count: get_u32("displayedLength")? + get_u32("waitingLength")?,
+                    paused: bool::try_from(paused)?,
+                    latest,
+                })
+            }
+            NotificationDaemon::Mako => {
+                let shown = list_notifications(connection, MAKO, MAKO_PATH, MAKO_INTERFACE, "ListNotifications")?;
+                let modes: Vec<String> = connection
+                    .call_method(Some(MAKO), MAKO_PATH, Some(MAKO_INTERFACE), "ListModes", &())?
+                    .body()
+                    .deserialize()?;
+
+                Ok(NotificationStatus {
+                    count: shown.len() as u32,
+                    paused: modes.iter().any(|mode| mode == MAKO_DND_MODE),
+                    latest: latest_summary(&shown),
+                })
+            }
+        }
+    }
+
+    /// Turn do-not-disturb on or off over a D-Bus connection.
+    pub fn set_paused(self, connection: &Connection, paused: bool) -> zbus::Result<()> {
+        match self {
+            NotificationDaemon::Dunst => {
+                connection.call_method(
+                    Some(DUNST),
+                    DUNST_PATH,
+                    Some("org.freedesktop.DBus.Properties"),
+                    "Set",
+                    &(DUNST_INTERFACE, "paused", Value::from(paused)),
+                )?;
+            }
+            NotificationDaemon::Mako => {
+                let mut modes: Vec<String> = connection
+                    .call_method(Some(MAKO), MAKO_PATH, Some(MAKO_INTERFACE), "ListModes", &())?
+                    .body()
+                    .deserialize()?;
+
+                modes.retain(|mode| mode != MAKO_DND_MODE);
+                if paused {
+                    modes.push(String::from(MAKO_DND_MODE));
+                }
+
+                connection.call_method(Some(MAKO), MAKO_PATH, Some(MAKO_INTERFACE), "SetModes", &(modes,))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const DUNST: &str = "org.freedesktop.Notifications";
+const DUNST_PATH: &str = "/org/freedesktop/Notifications";
+const DUNST_INTERFACE: &str = "org.dunstproject.cmd0";
+const MAKO: &str = "org.freedesktop.Notifications";
+const MAKO_PATH: &str = "/fr/emersion/Mako";
+const MAKO_INTERFACE: &str = "fr.emersion.Mako";
+const MAKO_DND_MODE: &str = "do-not-disturb";
+
+/// Read a D-Bus property.
+fn get_property(connection: &Connection, destination: &str, path: &str, interface: &str, name: &str)
+    -> zbus::Result<OwnedValue>
+{
+    connection
+        .call_method(Some(destination), path, Some("org.freedesktop.DBus.Properties"), "Get", &(interface, name))?
+        .body()
+        .deserialize()
+}
+
+/// Call `method` to get a list of notifications.
+fn list_notifications(connection: &Connection, destination: &str, path: &str, interface: &str, method: &str)
+    -> zbus::Result<Vec<HashMap<String, OwnedValue>>>
+{
+    connection
+        .call_method(Some(destination), path, Some(interface), method, &())?
+        .body()
+        .deserialize()
+}
+
+/// Get the summary of the notification with the highest id, the latest one.
+fn latest_summary(notifications: &[HashMap<String, OwnedValue>]) -> Option<String> {
+    let id = |notification: &HashMap<String, OwnedValue>| match notification.get("id").map(|id| &**id) {
+        Some(Value::U32(id)) => i64::from(*id),
+        Some(Value::I32(id)) => i64::from(*id),
+        _ => 0,
+    };
+
+    notifications
+        .iter()
+        .max_by_key(|notification| id(notification))
+        .and_then(|notification| notification.get("summary"))
+        .and_then(|summary| String::try_from(summary.try_clone().ok()?).ok())
+}
+
+/// Data stored by the systemd module.
+#[derive(Default)]
+pub struct SystemdData {
+    system: SystemdManager,
+    user: SystemdManager,
+}
+
+/// A systemd manager followed by the systemd module.
+#[derive(Default)]
+struct SystemdManager {
+    connection: Option<Connection>,
+    watcher: WatchData,
+    /// Failed units and configured units that are not active
+    problems: Vec<String>,
+}
+
+impl SystemdManager {
+    /// Look for problems again if a unit has changed since last time.
+    fn update(&mut self, connect: fn() -> zbus::Result<Connection>, units: &[&str]) {
+        if self.connection.is_none() {
+            match connect() {
+                Ok(connection) => self.connection = Some(connection),
+                Err(e) => {
+                    debug!("Failed to connect to systemd: {}", e);
+                    return;
+                }
+            }
+        }
+        let connection = self.connection.as_ref().unwrap();
+
+        self.watcher.watch(|changed| watch_units(connection.clone(), changed));
+
+        if !self.watcher.changed() {
+            return;
+        }
+
+        match unit_problems(connection, units) {
+            Ok(problems) => self.problems = problems,
+            Err(e) => {
+                // The problems found last time are kept until systemd can be
+                // asked again. Closing the connection ends the watcher as
+                // well, and a new one subscribes and asks again on reconnect
+                warn!("Failed to query systemd: {}", e);
+                if let Some(connection) = self.connection.take() {
+                    let _ = connection.close();
+                }
+                self.watcher = WatchData::default();
+            }
+        }
+    }
+}
+
+const SYSTEMD: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const SYSTEMD_MANAGER: &str = "org.freedesktop.systemd1.Manager";
+const SYSTEMD_UNIT: &str = "org.freedesktop.systemd1.Unit";
+
+/// A unit as listed by `ListUnits`: name, description, load state,
+/// active state, sub state, following, path, job id, job type and job path.
+pub type UnitStatus = (String, String, String, String, String, String, OwnedObjectPath, u32, String, OwnedObjectPath);
+
+/// Get the failed units of a systemd manager, followed by the units in
+/// `units` that are not active.
+pub fn unit_problems(connection: &Connection, units: &[&str]) -> zbus::Result<Vec<String>> {
+    let failed: Vec<UnitStatus> = connection
+        .call_method(Some(SYSTEMD), SYSTEMD_PATH, Some(SYSTEMD_MANAGER), "ListUnitsFiltered", &(&["failed"][..],))?
+        .body()
+        .deserialize()?;
+
+    let mut problems: Vec<String> = failed.into_iter().map(|unit| unit.0).collect();
+
+    for unit in units {
+        if problems.iter().any(|name| name == unit) {
+            continue;
+        }
+
+        let path: OwnedObjectPath = connection
+            .call_method(Some(SYSTEMD), SYSTEMD_PATH, Some(SYSTEMD_MANAGER), "LoadUnit", &(unit,))?
+            .body()
+            .deserialize()?;
+        let state = get_property(connection, SYSTEMD, &path, SYSTEMD_UNIT, "ActiveState")?;
+
+        if String::try_from(state)? != "active" {
+            problems.push(String::from(*unit));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Call `changed` in the background every time a unit of a systemd manager
+/// becomes active or inactive, until the connection is lost.
+pub fn watch_units(connection: Connection, changed: Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>> {
+    let watch = || -> zbus::Result<zbus::blocking::MessageIterator> {
+        let rule = zbus::MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .sender(SYSTEMD)?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .build();
+        let signals = zbus::blocking::MessageIterator::for_match_rule(rule, &connection, None)?;
+
+        // systemd only sends signals about units to subscribed clients
+        connection.call_method(Some(SYSTEMD), SYSTEMD_PATH, Some(SYSTEMD_MANAGER), "Subscribe", &())?;
+
+        Ok(signals)
+    };
+    let signals = watch().map_err(io::Error::other)?;
+
+    Ok(thread::spawn(move || {
+        for signal in signals {
+            let signal = match signal {
+                Ok(signal) => signal,
+                Err(_) => return,
+            };
+
+            let body = signal.body();
+            let changes: zbus::Result<(String, HashMap<String, OwnedValue>, Vec<String>)> = body.deserialize();
+
+            if let Ok((interface, properties, invalidated)) = changes {
+                if interface == SYSTEMD_UNIT
+                    && (properties.contains_key("ActiveState") || invalidated.iter().any(|p| p == "ActiveState"))
+                {
+                    changed();
+                }
+            }
+        }
+    }))
+}
+
+/// Data stored by the mail module.
+#[derive(Default)]
+pub struct MailData {
+    watcher: WatchData,
+    /// Unread messages of each Maildir
+    counts: Vec<(String, usize)>,
+    /// Unread messages of each command, counted in the background
+    command_counts: Arc<Mutex<Vec<(String, usize)>>>,
+    /// Whether the commands are running
+    counting: Arc<AtomicBool>,
+    /// Whether the mail has changed since the commands last started
+    commands_stale: bool,
+    /// When the commands last started
+    commands_started: Option<Instant>,
+}
+
+/// Count the new messages of a Maildir, the files in its `new` directory.
+///
+/// # Examples
+///
+/// ```
+/// use ba::count_maildir;
+/// use std::path::Path;
+///
+/// let inbox = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mail/INBOX");
+///
+/// // The inbox has two new messages
+/// assert_eq!(count_maildir(&inbox), 2);
+/// assert_eq!(count_maildir(&inbox.join("Missing")), 0);
+/// ```
+pub fn count_maildir(dir: &Path) -> usize {
+    read_dir(dir.join("new"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Run a command printing a number of messages, such as `notmuch count`.
+fn count_command(command: &str) -> usize {
+    match Command::new("sh").arg("-c").arg(command).stderr(Stdio::null()).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim().parse().unwrap_or(0),
+        Err(e) => {
+            warn!("Failed to run '{}': {}", command, e);
+            0
+        }
+    }
+}
+
+/// Data for modules that redraw the bar when something they show changes.
+#[derive(Default)]
+pub struct WatchData {
+    watcher: Option<JoinHandle<()>>,
+    changed: Arc<AtomicBool>,
+}
+
+impl WatchData {
+    /// Start watching with `start` unless already watching. `start` gets
+    /// a callback that redraws the bar, to call every time something changes.
+    pub fn watch<F>(&mut self, start: F)
+    where
+        F: FnOnce(Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>>,
+    {
+        if self.is_watching() {
+            return;
+        }
+
+        // Anything could have changed while not watching
+        self.changed.store(true, Ordering::SeqCst);
+
+        let changed = self.changed.clone();
+        let watcher = start(Box::new(move || {
+            changed.store(true, Ordering::SeqCst);
+            request_redraw();
+        }));
+
+        match watcher {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(e) => debug!("Failed to watch for changes: {}", e),
+        }
+    }
+
+    /// Whether something has changed since this was last called.
+    /// Always true when not watching, so that modules fall back to polling.
+    pub fn changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst) || !self.is_watching()
+    }
+
+    fn is_watching(&self) -> bool {
+        self.watcher.as_ref().is_some_and(|w| !w.is_finished())
+    }
+}
+
+/// Expand a leading `~/` in a path to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(path) => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+/// A screen backlight in `/sys/class/backlight`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backlight {
+    pub name: String,
+    pub brightness: u64,
+    pub max_brightness: u64,
+}
+
+impl Backlight {
+    /// The brightness in percent of the maximum brightness.
+    pub fn percent(&self) -> u32 {
+        (self.brightness * 100)
+            .checked_div(self.max_brightness)
+            .unwrap_or(0) as u32
+    }
+}
+
+/// Read the backlight called `name` in `<root>/sys/class/backlight`,
+/// or the first one if `name` is `None`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::read_backlight;
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/backlight");
+///
+/// // The backlight is at half brightness
+/// let light = read_backlight(root.to_str().unwrap(), None).unwrap();
+/// assert_eq!(light.name, "intel_backlight");
+/// assert_eq!(light.percent(), 50);
+///
+/// assert_eq!(read_backlight(root.to_str().unwrap(), Some("acpi_video0")), None);
+/// ```
+pub fn read_backlight(root: &str, name: Option<&str>) -> Option<Backlight> {
+    let dir = Path::new(root).join("sys/class/backlight");
+
+    let device = match name {
+        Some(name) => dir.join(name),
+        None => sorted_entries(&dir).into_iter().next()?,
+    };
+    let read = |file: &str| read_to_string(device.join(file)).ok()?.trim().parse().ok();
+
+    Some(Backlight {
+        name: device.file_name()?.to_string_lossy().into_owned(),
+        brightness: read("brightness")?,
+        max_brightness: read("max_brightness")?,
+    })
+}
+
+/// Set the brightness of a backlight in percent, with `BL_COMMAND`
+/// if it is set and through logind otherwise.
+fn set_brightness(light: &Backlight, percent: u32) {
+    if let Some(command) = BL_COMMAND {
+        run_command(&command.replace("{}", &percent.to_string()));
+        return;
+    }
+
+    let brightness = light.max_brightness * u64::from(percent) / 100;
+    let set = Connection::system()
+        .and_then(|connection| set_brightness_on(&connection, &light.name, brightness as u32));
+
+    if let Err(e) = set {
+        warn!("Failed to set the brightness of {}: {}", light.name, e);
+    }
+}
+
+/// Set the brightness of a backlight through logind over a D-Bus connection.
+pub fn set_brightness_on(connection: &Connection, name: &str, brightness: u32) -> zbus::Result<()> {
+    connection.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1/session/auto",
+        Some("org.freedesktop.login1.Session"),
+        "SetBrightness",
+        &("backlight", name, brightness),
+    )?;
+
+    Ok(())
+}
+
+/// The volume and mute state of a sound card or sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Volume {
+    pub percent: u32,
+    pub muted: bool,
+}
+
+/// Something that controls the volume, such as an ALSA mixer.
+pub trait Mixer: Send {
+    /// Read the current volume.
+    fn volume(&mut self) -> io::Result<Volume>;
+
+    /// Raise or lower the volume by some percent.
+    fn change_volume(&mut self, percent: i32) -> io::Result<()>;
+
+    /// Mute or unmute.
+    fn toggle_mute(&mut self) -> io::Result<()>;
+
+    /// Call `changed` in the background every time the volume changes.
+    /// The returned thread finishes if the mixer stops reporting changes.
+    fn watch(&mut self, changed: Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>>;
+}
+
+/// The mixers that the volume module can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MixerBackend {
+    /// The ALSA mixer control `VOL_ALSA_CONTROL` of card `VOL_ALSA_CARD`
+    Alsa,
+    /// The default sink of PulseAudio, or of PipeWire through pipewire-pulse
+    Pulse,
+}
+
+impl MixerBackend {
+    /// Create a mixer for this backend.
+    pub fn mixer(self) -> Box<dyn Mixer> {
+        match self {
+            MixerBackend::Alsa => Box::new(AlsaMixer),
+            MixerBackend::Pulse => Box::new(PulseMixer::default()),
+        }
+    }
+}
+
+/// Data stored by the volume module.
+#[derive(Default)]
+pub struct VolumeData {
+    mixer: Option<Box<dyn Mixer>>,
+    watcher: Option<JoinHandle<()>>,
+    unwatchable: bool,
+    changed: Arc<AtomicBool>,
+    volume: Option<Volume>,
+}
+
+impl VolumeData {
+    /// Use `mixer` instead of the one picked by `VOL_BACKEND`.
+    pub fn with_mixer(mixer: Box<dyn Mixer>) -> VolumeData {
+        VolumeData { mixer: Some(mixer), ..Default::default() }
+    }
+}
+
+/// An ALSA mixer control, through the control device of the card.
+pub struct AlsaMixer;
+
+impl AlsaMixer {
+    /// Open the card and get the range of `VOL_ALSA_CONTROL`.
+    fn open() -> io::Result<(alsa::Control, String, i64, i64)> {
+        let control = alsa::Control::open(VOL_ALSA_CARD)?;
+        let name = format!("{} Playback Volume", VOL_ALSA_CONTROL);
+        let (min, max, _) = control.range(&name)?;
+
+        Ok((control, name, min, max))
+    }
+}
+
+impl Mixer for AlsaMixer {
+    fn volume(&mut self) -> io::Result<Volume> {
+        let (control, name, min, max) = AlsaMixer::open()?;
+        let loudest = control.read(&name)?.into_iter().max().unwrap_or(min);
+
+        // Controls without a switch can't be muted
+        let switch = format!("{} Playback Switch", VOL_ALSA_CONTROL);
+        let muted = control.read(&switch).is_ok_and(|on| on.iter().all(|&on| on == 0));
+
+        Ok(Volume { percent: volume_percent(loudest - min, max - min), muted })
+    }
+
+    fn change_volume(&mut self, percent: i32) -> io::Result<()> {
+        let (control, name, min, max) = AlsaMixer::open()?;
+        let step = ((max - min) as f64 * f64::from(percent) / 100.0).round() as i64;
+
+        let values: Vec<i64> = control.read(&name)?.iter().map(|value| (value + step).clamp(min, max)).collect();
+        control.write(&name, &values)
+    }
+
+    fn toggle_mute(&mut self) -> io::Result<()> {
+        let control = alsa::Control::open(VOL_ALSA_CARD)?;
+        let switch = format!("{} Playback Switch", VOL_ALSA_CONTROL);
+
+        let on = control.read(&switch)?;
+        let unmute = on.iter().all(|&on| on == 0);
+        control.write(&switch, &vec![i64::from(unmute); on.len()])
+    }
+
+    fn watch(&mut self, changed: Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>> {
+        let mut control = alsa::Control::open(VOL_ALSA_CARD)?;
+        control.subscribe()?;
+
+        Ok(thread::spawn(move || {
+            while control.wait().is_ok() {
+                changed();
+            }
+        }))
+    }
+}
+
+/// The default sink of a PulseAudio server, or of PipeWire through
+/// pipewire-pulse. The connection is made when first needed.
+#[derive(Default)]
+pub struct PulseMixer {
+    connection: Option<pulse::Connection>,
+}
+
+impl PulseMixer {
+    /// Run `f` on the connection, connecting again once if it was lost.
+    fn with_connection<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: Fn(&mut pulse::Connection) -> io::Result<T>,
+    {
+        if let Some(connection) = self.connection.as_mut() {
+            match f(connection) {
+                Err(e) if e.kind() != io::ErrorKind::Other => debug!("Lost the PulseAudio connection: {}", e),
+                result => return result,
+            }
+        }
+
+        self.connection = None;
+        let connection = self.connection.insert(pulse::Connection::connect()?);
+        f(connection)
+    }
+}
+
+impl Mixer for PulseMixer {
+    fn volume(&mut self) -> io::Result<Volume> {
+        let sink = self.with_connection(|connection| connection.sink())?;
+        let loudest = sink.volumes.iter().copied().max().unwrap_or(0);
+
+        Ok(Volume {
+            percent: volume_percent(i64::from(loudest), i64::from(pulse::VOLUME_NORM)),
+            muted: sink.muted,
+        })
+    }
+
+    fn change_volume(&mut self, percent: i32) -> io::Result<()> {
+        let step = i64::from(pulse::VOLUME_NORM) * i64::from(percent) / 100;
+
+        self.with_connection(|connection| {
+            let sink = connection.sink()?;
+
+            // Scrolling goes up to 100%, but leaves louder sinks as they are
+            let volumes: Vec<u32> = sink
+                .volumes
+                .iter()
+                .map(|&volume| {
+                    let limit = i64::from(volume.max(pulse::VOLUME_NORM));
+                    (i64::from(volume) + step).clamp(0, limit) as u32
+                })
+                .collect();
+
+            connection.set_volume(&volumes)
+        })
+    }
+
+    fn toggle_mute(&mut self) -> io::Result<()> {
+        self.with_connection(|connection| {
+            let muted = connection.sink()?.muted;
+            connection.set_mute(!muted)
+        })
+    }
+
+    fn watch(&mut self, changed: Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>> {
+        let mut connection = pulse::Connection::connect()?;
+        connection.subscribe()?;
+
+        Ok(thread::spawn(move || {
+            while connection.wait().is_ok() {
+                changed();
+            }
+        }))
+    }
+}
+
+/// Turn a volume between 0 and `full` into a rounded percentage.
+fn volume_percent(volume: i64, full: i64) -> u32 {
+    if full <= 0 {
+        return 0;
+    }
+
+    ((volume.max(0) as f64 / full as f64) * 100.0).round() as u32
+}
+
+/// Parse the `avg10` stall percentage of the `some` line
+/// from the contents of a file in `/proc/pressure`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_pressure;
+///
+/// let pressure = "\
+/// some avg10=12.50 avg60=3.10 avg300=0.80 total=123456
+/// full avg10=2.00 avg60=0.50 avg300=0.10 total=23456
+/// ";
+///
+/// assert_eq!(parse_pressure(pressure), Some(12.5));
+/// ```
+pub fn parse_pressure(pressure: &str) -> Option<f64> {
+    pressure
+        .lines()
+        .find(|line| line.starts_with("some"))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// Disk counters stored by the disk module between updates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiskStats {
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    pub time: Option<Instant>,
+    /// Bytes read per second
+    pub read_rate: f64,
+    /// Bytes written per second
+    pub write_rate: f64,
+}
+
+impl DiskStats {
+    /// Update the rates with new counters of read and written bytes.
+    pub fn update(&mut self, read_bytes: u64, written_bytes: u64, now: Instant) {
+        if let Some(time) = self.time {
+            let seconds = now.duration_since(time).as_secs_f64();
+            if seconds > 0.0 {
+                // Counters go down when a disk goes away
+                self.read_rate = read_bytes.saturating_sub(self.read_bytes) as f64 / seconds;
+                self.write_rate = written_bytes.saturating_sub(self.written_bytes) as f64 / seconds;
+            }
+        }
+
+        self.read_bytes = read_bytes;
+        self.written_bytes = written_bytes;
+        self.time = Some(now);
+    }
+}
+
+/// Get how full the file system mounted at `point` is, in percent.
+pub fn disk_usage(point: &str) -> Option<u32> {
+    let path = CString::new(point).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        debug!("Failed to stat {}", point);
+        return None;
+    }
+
+    // Blocks reserved for root count as used, like df does
+    let used = stat.f_blocks.saturating_sub(stat.f_bfree) as f64;
+    let available = stat.f_bavail as f64;
+
+    if used + available <= 0.0 {
+        None
+    } else {
+        Some((used / (used + available) * 100.0).ceil() as u32)
+    }
+}
+
+/// Parse `(device, mount point)` pairs from the contents of `/proc/self/mounts`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_mounts;
+///
+/// let mounts = "\
+/// /dev/nvme0n1p2 / ext4 rw,relatime 0 0
+/// /dev/sdb1 /media/USB\\040Stick vfat rw 0 0
+/// ";
+///
+/// assert_eq!(parse_mounts(mounts), [
+///     (String::from("/dev/nvme0n1p2"), String::from("/")),
+///     (String::from("/dev/sdb1"), String::from("/media/USB Stick")),
+/// ]);
+/// ```
+pub fn parse_mounts(mounts: &str) -> Vec<(String, String)> {
+    // Whitespace in paths is escaped as octal
+    let unescape = |path: &str| path
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\");
+
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((unescape(fields.next()?), unescape(fields.next()?)))
+        })
+        .collect()
+}
+
+/// Parse read and written bytes of every device from the contents of `/proc/diskstats`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_diskstats;
+///
+/// let diskstats = "\
+///  259       0 nvme0n1 1000 0 2000 0 500 0 4000 0 0 0 0 0 0 0 0 0 0
+///  259       2 nvme0n1p2 900 0 1800 0 400 0 3000 0 0 0 0 0 0 0 0 0 0
+/// ";
+///
+/// assert_eq!(parse_diskstats(diskstats)["nvme0n1p2"], (1800 * 512, 3000 * 512));
+/// ```
+pub fn parse_diskstats(diskstats: &str) -> HashMap<String, (u64, u64)> {
+    // Sectors are always 512 bytes here, no matter the disk
+    let sector = 512;
+
+    diskstats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let read: u64 = fields.get(5)?.parse().ok()?;
+            let written: u64 = fields.get(9)?.parse().ok()?;
+            Some((String::from(fields[2]), (read * sector, written * sector)))
+        })
+        .collect()
+}
+
+/// Get the name of a block device in `/proc/diskstats` from its path,
+/// following links such as `/dev/mapper/root` to `dm-0`.
+fn block_device(device: &str) -> String {
+    let path = std::fs::canonicalize(device).unwrap_or_else(|_| PathBuf::from(device));
+
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Remove repeated block devices, such as those of several btrfs subvolumes,
+/// and partitions whose disk is listed as well, so that no I/O is counted
+/// twice. Disks and their partitions are found in `<root>/sys/block`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::distinct_disks;
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/disk");
+/// // nvme0n1 and sda have one partition each
+/// let devices = ["nvme0n1p2", "sda1", "nvme0n1p2", "nvme0n1"].iter().map(|d| d.to_string()).collect();
+///
+/// assert_eq!(distinct_disks(root.to_str().unwrap(), devices), ["nvme0n1", "sda1"]);
+/// ```
+pub fn distinct_disks(root: &str, mut devices: Vec<String>) -> Vec<String> {
+    devices.sort();
+    devices.dedup();
+
+    let block = Path::new(root).join("sys/block");
+    let disks = devices.clone();
+    devices.retain(|device| {
+        !disks.iter().any(|disk| disk != device && block.join(disk).join(device).exists())
+    });
+
+    devices
+}
+
+/// A temperature sensor from hwmon or a thermal zone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sensor {
+    /// The hwmon `name`, or `thermal` for thermal zones
+    pub chip: String,
+    /// The `temp*_label` of the sensor, or the type of a thermal zone
+    pub label: String,
+    pub celsius: f64,
+    pub critical: Option<f64>,
+}
+
+/// Read all temperature sensors in `<root>/sys/class/hwmon`
+/// and `<root>/sys/class/thermal`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{read_fans, read_temperatures};
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/temperature");
+///
+/// // A coretemp chip with a fan and an ACPI thermal zone
+/// let sensors = read_temperatures(root.to_str().unwrap());
+/// let found: Vec<_> = sensors.iter()
+///     .map(|s| (s.chip.as_str(), s.label.as_str(), s.celsius, s.critical))
+///     .collect();
+/// assert_eq!(found, [
+///     ("coretemp", "Package id 0", 54.0, Some(100.0)),
+///     ("coretemp", "coretemp temp2", 51.0, None),
+///     ("thermal", "acpitz", 27.8, Some(119.0)),
+/// ]);
+///
+/// assert_eq!(read_fans(root.to_str().unwrap()), [(String::from("cpu_fan"), 2100)]);
+/// ```
+pub fn read_temperatures(root: &str) -> Vec<Sensor> {
+    let mut sensors = Vec::new();
+
+    // Temperatures are in millidegrees
+    let read = |path: &Path| read_to_string(path).map(|s| String::from(s.trim())).ok();
+    let degrees = |path: &Path| read(path)?.parse::<f64>().ok().map(|t| t / 1000.0);
+
+    for chip in sorted_entries(&Path::new(root).join("sys/class/hwmon")) {
+        let name = read(&chip.join("name")).unwrap_or_default();
+
+        for input in hwmon_inputs(&chip, "temp") {
+            let celsius = match degrees(&chip.join(format!("{}_input", input))) {
+                Some(celsius) => celsius,
+                None => continue,
+            };
+
+            sensors.push(Sensor {
+                chip: name.clone(),
+                label: read(&chip.join(format!("{}_label", input)))
+                    .unwrap_or_else(|| format!("{} {}", name, input)),
+                celsius,
+                critical: degrees(&chip.join(format!("{}_crit", input))),
+            });
+        }
+    }
+
+    for zone in sorted_entries(&Path::new(root).join("sys/class/thermal")) {
+        let celsius = match degrees(&zone.join("temp")) {
+            Some(celsius) if zone.to_string_lossy().contains("thermal_zone") => celsius,
+            _ => continue,
+        };
+
+        // The critical temperature is one of the trip points
+        let critical = (0..)
+            .map(|i| zone.join(format!("trip_point_{}_type", i)))
+            .take_while(|path| path.exists())
+            .enumerate()
+            .find(|(_, path)| read(path).as_deref() == Some("critical"))
+            .and_then(|(i, _)| degrees(&zone.join(format!("trip_point_{}_temp", i))));
+
+        sensors.push(Sensor {
+            chip: String::from("thermal"),
+            label: read(&zone.join("type")).unwrap_or_default(),
+            celsius,
+            critical,
+        });
+    }
+
+    sensors
+}
+
+/// Read the speed of all fans in `<root>/sys/class/hwmon`
+/// as `(label, rpm)`, see `read_temperatures`.
+pub fn read_fans(root: &str) -> Vec<(String, u32)> {
+    let mut fans = Vec::new();
+
+    for chip in sorted_entries(&Path::new(root).join("sys/class/hwmon")) {
+        let name = read_to_string(chip.join("name")).unwrap_or_default();
+
+        for input in hwmon_inputs(&chip, "fan") {
+            let rpm = read_to_string(chip.join(format!("{}_input", input)))
+                .ok()
+                .and_then(|rpm| rpm.trim().parse().ok());
+            let label = read_to_string(chip.join(format!("{}_label", input)))
+                .map(|label| String::from(label.trim()))
+                .unwrap_or_else(|_| format!("{} {}", name.trim(), input));
+
+            if let Some(rpm) = rpm {
+                fans.push((label, rpm));
+            }
+        }
+    }
+
+    fans
+}
+
+/// Pick the first sensor matching a label or chip name in `wanted`,
+/// or the first sensor if none match.
+fn pick_sensor<'a>(sensors: &'a [Sensor], wanted: &[&str]) -> Option<&'a Sensor> {
+    wanted
+        .iter()
+        .find_map(|w| sensors.iter().find(|s| s.label == *w || s.chip == *w))
+        .or_else(|| sensors.first())
+}
+
+/// Names of hwmon inputs of a kind, such as `temp1` and `temp2` for `temp`.
+fn hwmon_inputs(chip: &Path, kind: &str) -> Vec<String> {
+    let mut inputs: Vec<(u32, String)> = read_dir(chip)
+        .map(|entries| entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let number = name.strip_prefix(kind)?.strip_suffix("_input")?.parse().ok()?;
+                Some((number, format!("{}{}", kind, number)))
+            })
+            .collect())
+        .unwrap_or_default();
+
+    inputs.sort();
+    inputs.into_iter().map(|(_, name)| name).collect()
+}
+
+/// Paths in a directory, sorted by name.
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<_> = read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+
+    entries.sort();
+    entries
+}
+
+/// Ways of counting used memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemUsed {
+    /// Memory that is not available for new programs, `MemTotal - MemAvailable`
+    Unavailable,
+    /// Memory used by programs, not counting buffers and caches,
+    /// `MemTotal - MemFree - Buffers - Cached - SReclaimable`
+    Programs,
+    /// Memory that is not free, counting buffers and caches, `MemTotal - MemFree`
+    NotFree,
+}
+
+impl MemUsed {
+    /// Calculate used memory in KiB from parsed `/proc/meminfo`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::{parse_meminfo, MemUsed};
+    ///
+    /// let meminfo = parse_meminfo("\
+    /// MemTotal:       16000000 kB
+    /// MemFree:         4000000 kB
+    /// MemAvailable:   10000000 kB
+    /// Buffers:          500000 kB
+    /// Cached:          5000000 kB
+    /// SReclaimable:     500000 kB
+    /// SwapTotal:       2000000 kB
+    /// SwapFree:        2000000 kB
+    /// ");
+    ///
+    /// assert_eq!(MemUsed::Unavailable.used(&meminfo), 6000000);
+    /// assert_eq!(MemUsed::Programs.used(&meminfo), 6000000);
+    /// assert_eq!(MemUsed::NotFree.used(&meminfo), 12000000);
+    /// ```
+    pub fn used(self, meminfo: &HashMap<String, u64>) -> u64 {
+        let get = |key: &str| meminfo.get(key).copied().unwrap_or(0);
+        let total = get("MemTotal");
+
+        let unused = match self {
+            MemUsed::Unavailable => get("MemAvailable"),
+            MemUsed::Programs => get("MemFree") + get("Buffers") + get("Cached") + get("SReclaimable"),
+            MemUsed::NotFree => get("MemFree"),
+        };
+
+        total.saturating_sub(unused)
+    }
+}
+
+/// Parse the contents of `/proc/meminfo` into sizes in KiB.
+pub fn parse_meminfo(meminfo: &str) -> HashMap<String, u64> {
+    meminfo
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let value = value.split_whitespace().next()?.parse().ok()?;
+            Some((String::from(key.trim()), value))
+        })
+        .collect()
+}
+
+/// Get the memory used by all zram devices in bytes, from
+/// `mem_used_total` in `<root>/sys/block/zram*/mm_stat`.
+pub fn zram_used(root: &str) -> Option<u64> {
+    let devices = read_dir(Path::new(root).join("sys/block")).ok()?;
+
+    let used: Vec<u64> = devices
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("zram"))
+        .filter_map(|entry| read_to_string(entry.path().join("mm_stat")).ok())
+        .filter_map(|stat| stat.split_whitespace().nth(2)?.parse().ok())
+        .collect();
+
+    if used.is_empty() {
+        None
+    } else {
+        Some(used.iter().sum())
+    }
+}
+
+/// Cpu times stored by the cpu module between updates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuStats {
+    /// `(idle, total)` times of all cpus, followed by each core
+    pub times: Vec<(u64, u64)>,
+    /// Recent loads of all cpus, oldest first
+    pub history: VecDeque<u32>,
+}
+
+impl CpuStats {
+    /// Store new `(idle, total)` times and return the load in percent since
+    /// the last update, for all cpus followed by each core.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::CpuStats;
+    ///
+    /// let mut stats = CpuStats::default();
+    /// // There is nothing to compare with the first time
+    /// assert_eq!(stats.update(vec![(100, 200), (50, 100)]), [0, 0]);
+    /// assert_eq!(stats.update(vec![(150, 400), (50, 200)]), [75, 100]);
+    /// // Times that haven't changed don't divide by zero
+    /// assert_eq!(stats.update(vec![(150, 400), (50, 200)]), [0, 0]);
+    /// ```
+    pub fn update(&mut self, times: Vec<(u64, u64)>) -> Vec<u32> {
+        let loads: Vec<u32> = times
+            .iter()
+            .enumerate()
+            .map(|(i, (idle, total))| {
+                let (last_idle, last_total) = self.times.get(i).copied().unwrap_or((*idle, *total));
+                let total = total.saturating_sub(last_total);
+                let idle = idle.saturating_sub(last_idle).min(total);
+
+                if total == 0 {
+                    0
+                } else {
+                    100 - (idle as f64 / total as f64 * 100.0).round() as u32
+                }
+            })
+            .collect();
+
+        self.times = times;
+        self.history.push_back(loads.first().copied().unwrap_or(0));
+        while self.history.len() > CPU_HISTORY {
+            self.history.pop_front();
+        }
+
+        loads
+    }
+}
+
+/// Parse the `(idle, total)` times of all cpus, followed by
+/// each core, from the contents of `/proc/stat`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_proc_stat;
+///
+/// let stat = "\
+/// cpu  10 1 5 100 4 0 0 0 0 0
+/// cpu0 5 0 3 50 2 0 0 0 0 0
+/// cpu1\t5 1 2 50 2 0 0 0 0 0
+/// intr 1234 0 0
+/// ";
+///
+/// assert_eq!(parse_proc_stat(stat), [(100, 120), (50, 60), (50, 60)]);
+/// ```
+pub fn parse_proc_stat(stat: &str) -> Vec<(u64, u64)> {
+    stat.lines()
+        .filter(|line| line.starts_with("cpu"))
+        .filter_map(|line| {
+            // user nice system idle iowait irq softirq
+            let times: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .take(7)
+                .map(|x| x.parse().unwrap_or(0))
+                .collect();
+
+            Some((*times.get(3)?, times.iter().sum()))
+        })
+        .collect()
+}
+
+/// Get the average current frequency of all cores in kHz.
+pub fn cpu_frequency(root: &str) -> Option<f64> {
+    let cpus = read_dir(Path::new(root).join("sys/devices/system/cpu")).ok()?;
+
+    let frequencies: Vec<f64> = cpus
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("cpu") && name[3..].chars().all(|c| c.is_ascii_digit())
+        })
+        .filter_map(|entry| read_to_string(entry.path().join("cpufreq/scaling_cur_freq")).ok())
+        .filter_map(|frequency| frequency.trim().parse().ok())
+        .collect();
+
+    if frequencies.is_empty() {
+        None
+    } else {
+        Some(frequencies.iter().sum::<f64>() / frequencies.len() as f64)
+    }
+}
+
+/// Draw percentages as a sparkline, using blocks or
+/// braille if `CPU_BRAILLE` is set.
+fn sparkline(values: &[u32]) -> String {
+    if CPU_BRAILLE {
+        braille_sparkline(values)
+    } else {
+        block_sparkline(values)
+    }
+}
+
+/// Draw percentages as a sparkline of blocks, one for each value.
+///
+/// # Examples
+///
+/// ```
+/// use ba::block_sparkline;
+///
+/// assert_eq!(block_sparkline(&[0, 20, 50, 100]), "▁▂▄█");
+/// ```
+pub fn block_sparkline(values: &[u32]) -> String {
+    let blocks = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    values
+        .iter()
+        .map(|value| blocks[(value.min(&100) * (blocks.len() as u32 - 1) / 100) as usize])
+        .collect()
+}
+
+/// Draw percentages as a sparkline of braille, two values per character.
+///
+/// # Examples
+///
+/// ```
+/// use ba::braille_sparkline;
+///
+/// assert_eq!(braille_sparkline(&[0, 100, 50]), "⣸⡄");
+/// ```
+pub fn braille_sparkline(values: &[u32]) -> String {
+    // Dots from the bottom up, for the left and the right column
+    let left = [0x40, 0x04, 0x02, 0x01];
+    let right = [0x80, 0x20, 0x10, 0x08];
+
+    // Always show at least one dot, like the lowest block
+    let dots = |value: u32, column: &[u32; 4]| -> u32 {
+        let height = 1 + value.min(100) as usize * 3 / 100;
+        column[..height].iter().sum()
+    };
+
+    values
+        .chunks(2)
+        .map(|pair| {
+            let mut pattern = dots(pair[0], &left);
+            if let Some(value) = pair.get(1) {
+                pattern |= dots(*value, &right);
+            }
+            std::char::from_u32(0x2800 + pattern).unwrap_or(' ')
+        })
+        .collect()
+}
+
+/// What the batteries are doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatStatus {
+    Charging,
+    Discharging,
+    Full,
+    /// Plugged in, but not charging, for example because of a charge threshold
+    NotCharging,
+    Unknown,
+}
+
+/// All batteries combined into one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatteryInfo {
+    /// Charge in percent
+    pub capacity: u32,
+    pub status: BatStatus,
+    /// `true` if an AC adapter is plugged in
+    pub ac_online: bool,
+    /// Time until the batteries are empty when discharging, or full when charging
+    pub time_left: Option<Duration>,
+}
+
+/// Read and combine all batteries in `<root>/sys/class/power_supply`.
+/// Returns `None` if there are no batteries.
+///
+/// Batteries are weighted by their size, or all count the same when one
+/// doesn't report its size. The status is refined by looking at the AC
+/// adapter when the batteries don't report it.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{read_batteries, BatStatus};
+/// use std::path::Path;
+/// use std::time::Duration;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/battery");
+///
+/// // A discharging battery, an idle one and an unplugged AC adapter
+/// let battery = read_batteries(root.to_str().unwrap()).unwrap();
+/// assert_eq!(battery.capacity, 67);
+/// assert_eq!(battery.status, BatStatus::Discharging);
+/// assert!(!battery.ac_online);
+/// assert_eq!(battery.time_left, Some(Duration::from_secs(4 * 3600)));
+///
+/// // A battery at 75% and one only reporting that it's at 20%
+/// let battery = read_batteries(root.join("unsized").to_str().unwrap()).unwrap();
+/// assert_eq!(battery.capacity, 48);
+/// assert_eq!(battery.time_left, None);
+///
+/// assert_eq!(read_batteries("/nonexistent"), None);
+/// ```
+pub fn read_batteries(root: &str) -> Option<BatteryInfo> {
+    let supply = Path::new(root).join("sys/class/power_supply");
+    let mut supplies: Vec<_> = read_dir(&supply).ok()?.flatten().map(|e| e.path()).collect();
+    supplies.sort();
+
+    let mut ac_online = false;
+    let mut statuses = Vec::new();
+    let mut energies = Vec::new();
+    let mut capacities = Vec::new();
+    let mut power = 0.0;
+
+    for path in supplies {
+        let read = |file: &str| read_to_string(path.join(file))
+            .map(|s| String::from(s.trim()))
+            .unwrap_or_default();
+        let number = |file: &str| read(file).parse::<f64>().ok();
+
+        match read("type").as_str() {
+            "Mains" => {
+                ac_online |= read("online") == "1";
+                continue;
+            }
+            "Battery" if read("present") != "0" => {}
+            _ => continue,
+        }
+
+        // Batteries report either energy (µWh) and power (µW)
+        // or charge (µAh) and current (µA), which need the voltage (µV)
+        let volts = number("voltage_now").unwrap_or(0.0) / 1e6;
+        energies.push(match (number("energy_now"), number("energy_full")) {
+            (Some(now), Some(full)) => Some((now, full)),
+            _ => match (number("charge_now"), number("charge_full")) {
+                (Some(now), Some(full)) if volts > 0.0 => Some((now * volts, full * volts)),
+                _ => None,
+            },
+        });
+        capacities.push(number("capacity"));
+        power += number("power_now")
+            .or_else(|| number("current_now").map(|current| current * volts))
+            .unwrap_or(0.0)
+            .abs();
+
+        statuses.push(match read("status").as_str() {
+            "Charging" => BatStatus::Charging,
+            "Discharging" => BatStatus::Discharging,
+            "Full" => BatStatus::Full,
+            "Not charging" => BatStatus::NotCharging,
+            _ => BatStatus::Unknown,
+        });
+    }
+
+    // Without every size, percentages can't be added up with energies,
+    // so every battery counts as the same size and the time left is unknown
+    let sized = energies.iter().all(Option::is_some);
+    let (energy_now, energy_full) = if sized {
+        energies.iter().flatten().fold((0.0, 0.0), |(now, full), (n, f)| (now + n, full + f))
+    } else {
+        let percents = energies.iter().zip(&capacities).map(|(energy, capacity)| match energy {
+            Some((now, full)) if *full > 0.0 => now / full * 100.0,
+            _ => capacity.unwrap_or(0.0),
+        });
+        (percents.sum(), 100.0 * energies.len() as f64)
+    };
+
+    if statuses.is_empty() || energy_full <= 0.0 {
+        return None;
+    }
+
+    let has = |status| statuses.contains(&status);
+    let status = if has(BatStatus::Charging) {
+        BatStatus::Charging
+    } else if has(BatStatus::Discharging) {
+        BatStatus::Discharging
+    } else if statuses.iter().all(|s| *s == BatStatus::Full) {
+        BatStatus::Full
+    } else if has(BatStatus::NotCharging) || ac_online {
+        BatStatus::NotCharging
+    } else if has(BatStatus::Full) {
+        BatStatus::Full
+    } else {
+        BatStatus::Unknown
+    };
+
+    let hours = match status {
+        BatStatus::Discharging if sized && power > 0.0 => Some(energy_now / power),
+        BatStatus::Charging if sized && power > 0.0 => Some((energy_full - energy_now).max(0.0) / power),
+        _ => None,
+    };
+
+    Some(BatteryInfo {
+        capacity: (energy_now / energy_full * 100.0).round().min(100.0) as u32,
+        status,
+        ac_online,
+        time_left: hours.map(|hours| Duration::from_secs((hours * 3600.0).round() as u64)),
+    })
+}
+
+/// Low battery alert state, stored by the battery module between updates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatAlerts {
+    /// Levels in `BAT_ALERTS` that have been alerted about
+    pub alerted: Vec<u32>,
+    /// When `BAT_CRITICAL_COMMAND` is run
+    pub critical_at: Option<Instant>,
+    /// `true` once `BAT_CRITICAL_COMMAND` has been run
+    pub critical_done: bool,
+}
+
+impl BatAlerts {
+    /// Check the charge against `BAT_ALERTS`. Returns the level to alert
+    /// about if the charge just dropped below one, once per crossing.
+    ///
+    /// A level can alert again once the charge has risen
+    /// `BAT_ALERT_HYSTERESIS` above it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::BatAlerts;
+    ///
+    /// let mut alerts = BatAlerts::default();
+    /// assert_eq!(alerts.check(25, true), None);
+    /// assert_eq!(alerts.check(20, true), Some(20));
+    /// assert_eq!(alerts.check(19, true), None);
+    ///
+    /// // Going back and forth around the level doesn't alert again
+    /// assert_eq!(alerts.check(21, false), None);
+    /// assert_eq!(alerts.check(20, true), None);
+    ///
+    /// // Dropping past several levels at once alerts about the lowest
+    /// assert_eq!(alerts.check(30, false), None);
+    /// assert_eq!(alerts.check(8, true), Some(10));
+    /// ```
+    pub fn check(&mut self, capacity: u32, discharging: bool) -> Option<u32> {
+        self.alerted.retain(|level| capacity < level + BAT_ALERT_HYSTERESIS);
+
+        if !discharging {
+            return None;
+        }
+
+        let crossed: Vec<u32> = BAT_ALERTS
+            .iter()
+            .copied()
+            .filter(|level| capacity <= *level && !self.alerted.contains(level))
+            .collect();
+
+        self.alerted.extend(&crossed);
+        crossed.into_iter().min()
+    }
+
+    /// Count down to `BAT_CRITICAL_COMMAND` while the charge is at
+    /// `BAT_CRITICAL` or below. Returns the time left, which is zero
+    /// once the command should run. The countdown stops when charging.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::BatAlerts;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let start = Instant::now();
+    /// let mut alerts = BatAlerts::default();
+    /// assert_eq!(alerts.countdown(50, true, start), None);
+    ///
+    /// let left = alerts.countdown(4, true, start).unwrap();
+    /// assert!(left > Duration::from_secs(0));
+    /// assert_eq!(alerts.countdown(4, true, start + left), Some(Duration::from_secs(0)));
+    ///
+    /// // Plugging in stops the countdown
+    /// assert_eq!(alerts.countdown(4, false, start + left), None);
+    /// ```
+    pub fn countdown(&mut self, capacity: u32, discharging: bool, now: Instant)
+        -> Option<Duration>
+    {
+        if !discharging || capacity > BAT_CRITICAL {
+            self.critical_at = None;
+            self.critical_done = false;
+            return None;
+        }
+
+        let at = *self.critical_at
+            .get_or_insert(now + Duration::from_secs(BAT_CRITICAL_COUNTDOWN));
+
+        Some(at.saturating_duration_since(now))
+    }
+}
+
+/// Send a desktop notification on the session bus.
+/// The notification is sent in the background, failures are logged.
+pub fn notify(summary: &str, body: &str, critical: bool) {
+    let (summary, body) = (String::from(summary), String::from(body));
+
+    thread::spawn(move || {
+        let sent = Connection::session()
+            .and_then(|connection| notify_on(&connection, &summary, &body, critical));
+
+        if let Err(e) = sent {
+            warn!("Failed to send notification '{}': {}", summary, e);
+        }
+    });
+}
+
+/// Send a desktop notification over a D-Bus connection and return its id.
+pub fn notify_on(connection: &Connection, summary: &str, body: &str, critical: bool)
+    -> zbus::Result<u32>
+{
+    // Urgency is 1 for normal and 2 for critical notifications
+    let mut hints = HashMap::new();
+    hints.insert("urgency", Value::U8(if critical { 2 } else { 1 }));
+
+    let reply = connection.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        &("ba", 0u32, "", summary, body, Vec::<&str>::new(), hints, -1i32),
+    )?;
+
+    reply.body().deserialize()
+}
+
+/// Run a shell command in the background.
+pub fn run_command(command: &str) {
+    match Command::new("sh").arg("-c").arg(command).spawn() {
+        // Wait for the command in the background so that it is reaped
+        Ok(mut child) => { thread::spawn(move || child.wait()); }
+        Err(e) => warn!("Failed to run '{}': {}", command, e),
+    }
+}
+
+/// Kinds of network interfaces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetKind {
+    Wireless,
+    Ethernet,
+    Vpn,
+    Virtual,
+    Loopback,
+}
+
+/// A network interface found in `/sys/class/net`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub kind: NetKind,
+    pub up: bool,
+}
+
+/// Read all network interfaces in `<root>/sys/class/net`, sorted by name.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{read_interfaces, NetKind};
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/net");
+///
+/// let interfaces = read_interfaces(root.to_str().unwrap());
+/// let kinds: Vec<_> = interfaces.iter().map(|i| (i.name.as_str(), i.kind, i.up)).collect();
+/// assert_eq!(kinds, [
+///     ("eth0", NetKind::Ethernet, false),
+///     ("lo", NetKind::Loopback, true),
+///     ("wg0", NetKind::Vpn, true),
+///     ("wlan0", NetKind::Wireless, true),
+/// ]);
+/// ```
+pub fn read_interfaces(root: &str) -> Vec<Interface> {
+    let net = Path::new(root).join("sys/class/net");
+    let entries = match read_dir(&net) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read {}: {}", net.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut interfaces: Vec<Interface> = entries
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            let read = |file: &str| read_to_string(path.join(file))
+                .map(|s| String::from(s.trim()))
+                .unwrap_or_default();
+
+            // ARPHRD_LOOPBACK is 772, ARPHRD_NONE (used by tunnels) is 65534
+            let kind = if name == "lo" || read("type") == "772" {
+                NetKind::Loopback
+            } else if path.join("wireless").exists() || path.join("phy80211").exists() {
+                NetKind::Wireless
+            } else if path.join("tun_flags").exists()
+                || read("type") == "65534"
+                || ["wg", "tun", "tap", "ppp"].iter().any(|p| name.starts_with(p))
+            {
+                NetKind::Vpn
+            } else if path.join("device").exists() {
+                NetKind::Ethernet
+            } else {
+                NetKind::Virtual
+            };
+
+            // Tunnels don't report their state, but they have the IFF_UP flag
+            let flags = u32::from_str_radix(read("flags").trim_start_matches("0x"), 16)
+                .unwrap_or(0);
+            let up = match read("operstate").as_str() {
+                "up" => true,
+                "unknown" => flags & 1 != 0 || kind == NetKind::Loopback,
+                _ => false,
+            };
+
+            Interface { name, kind, up }
+        })
+        .collect();
+
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+/// Get the name of the interface with the default route
+/// from `<root>/proc/net/route`, preferring the lowest metric.
+///
+/// # Examples
+///
+/// ```
+/// use ba::default_route_interface;
+/// use std::path::Path;
+///
+/// let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/net");
+///
+/// // Both wlan0 and eth0 have a default route, and eth0 has the lower metric
+/// assert_eq!(default_route_interface(root.to_str().unwrap()), Some(String::from("eth0")));
+/// ```
+pub fn default_route_interface(root: &str) -> Option<String> {
+    let routes = read_to_string(Path::new(root).join("proc/net/route")).ok()?;
+
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            let metric: u32 = fields.get(6)?.parse().ok()?;
+
+            // Only routes that are up (RTF_UP) to 0.0.0.0
+            if fields[1] == "00000000" && flags & 1 != 0 {
+                Some((metric, String::from(fields[0])))
+            } else {
+                None
+            }
+        })
+        .min()
+        .map(|(_, name)| name)
+}
+
+/// Pick the interface to show on the bar. The default route interface
+/// is preferred, then wireless, ethernet and other interfaces that are up.
+fn active_interface(interfaces: &[Interface], default: Option<String>) -> Option<&Interface> {
+    let up = || interfaces.iter().filter(|i| i.up && i.kind != NetKind::Loopback);
+
+    default
+        .and_then(|name| up().find(|i| i.name == name))
+        .or_else(|| up().find(|i| i.kind == NetKind::Wireless))
+        .or_else(|| up().find(|i| i.kind == NetKind::Ethernet))
+        .or_else(|| up().next())
+}
+
+/// Data stored by the network module between updates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetStats {
+    /// The interface the counters belong to
+    pub interface: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub time: Option<Instant>,
+    /// Bytes received per second
+    pub rx_rate: f64,
+    /// Bytes sent per second
+    pub tx_rate: f64,
+    /// Whether the rates have been measured, which takes two updates
+    pub has_rates: bool,
+}
+
+impl NetStats {
+    /// Update the rates with new byte counters for `interface`.
+    /// The counters start over when the interface changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::NetStats;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let start = Instant::now();
+    /// let mut stats = NetStats::default();
+    /// stats.update("eth0", 1000, 500, start);
+    /// assert_eq!(stats.rx_rate, 0.0);
+    ///
+    /// stats.update("eth0", 3000, 1500, start + Duration::from_secs(2));
+    /// assert_eq!((stats.rx_rate, stats.tx_rate), (1000.0, 500.0));
+    ///
+    /// // Later rates are smoothed with NET_SMOOTHING
+    /// stats.update("eth0", 3000, 1500, start + Duration::from_secs(4));
+    /// assert_eq!((stats.rx_rate, stats.tx_rate), (500.0, 250.0));
+    /// ```
+    pub fn update(&mut self, interface: &str, rx_bytes: u64, tx_bytes: u64, now: Instant) {
+        if self.interface != interface {
+            *self = NetStats::default();
+            self.interface = String::from(interface);
+        }
+
+        if let Some(time) = self.time {
+            let seconds = now.duration_since(time).as_secs_f64();
+            if seconds > 0.0 {
+                // Counters can go backwards when the interface is reset
+                let rx = rx_bytes.saturating_sub(self.rx_bytes) as f64 / seconds;
+                let tx = tx_bytes.saturating_sub(self.tx_bytes) as f64 / seconds;
+
+                // The first rate has nothing to be smoothed with
+                if self.has_rates {
+                    self.rx_rate = NET_SMOOTHING * self.rx_rate + (1.0 - NET_SMOOTHING) * rx;
+                    self.tx_rate = NET_SMOOTHING * self.tx_rate + (1.0 - NET_SMOOTHING) * tx;
+                } else {
+                    self.rx_rate = rx;
+                    self.tx_rate = tx;
+                    self.has_rates = true;
+                }
+            }
+        }
+
+        self.rx_bytes = rx_bytes;
+        self.tx_bytes = tx_bytes;
+        self.time = Some(now);
+    }
+}
+
+/// Signal of a wireless interface, from `/proc/net/wireless`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WirelessSignal {
+    /// Link quality in percent
+    pub quality: u32,
+    /// Signal level in dBm
+    pub level: f64,
+}
+
+/// Parse the signal of `interface` from the contents of `/proc/net/wireless`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::parse_wireless;
+/// use std::path::Path;
+///
+/// let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/net/proc/net/wireless");
+/// let wireless = std::fs::read_to_string(fixture).unwrap();
+///
+/// let signal = parse_wireless(&wireless, "wlan0").unwrap();
+/// assert_eq!(signal.quality, 80);
+/// assert_eq!(signal.level, -54.0);
+/// assert_eq!(parse_wireless(&wireless, "eth0"), None);
+/// ```
+pub fn parse_wireless(wireless: &str, interface: &str) -> Option<WirelessSignal> {
+    // The quality is out of 70 in most drivers
+    let max_quality = 70.0;
+
+    wireless.lines().skip(2).find_map(|line| {
+        let (name, values) = line.split_once(':')?;
+        if name.trim() != interface {
+            return None;
+        }
+
+        // Values are followed by a dot if they were updated since the last read
+        let mut values = values.split_whitespace().skip(1).map(|v| v.trim_end_matches('.'));
+        let link: f64 = values.next()?.parse().ok()?;
+        let level: f64 = values.next()?.parse().ok()?;
+        let quality = (link / max_quality * 100.0).round().clamp(0.0, 100.0) as u32;
+
+        Some(WirelessSignal { quality, level })
+    })
+}
+
+/// Read the received and sent byte counters of an interface
+/// from `<root>/sys/class/net/<name>/statistics`.
+pub fn read_net_bytes(root: &str, name: &str) -> Option<(u64, u64)> {
+    let stats = Path::new(root).join("sys/class/net").join(name).join("statistics");
+    let read = |file: &str| read_to_string(stats.join(file)).ok()?.trim().parse().ok();
+
+    Some((read("rx_bytes")?, read("tx_bytes")?))
+}
+
+/// Get the IPv4 and IPv6 addresses of an interface.
+pub fn interface_addresses(name: &str) -> Vec<IpAddr> {
+    let mut addresses = Vec::new();
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+
+    // getifaddrs gives a linked list that has to be freed with freeifaddrs
+    unsafe {
+        if libc::getifaddrs(&mut ifaddrs) != 0 {
+            warn!("Failed to get interface addresses");
+            return addresses;
+        }
+
+        let mut current = ifaddrs;
+        while let Some(ifaddr) = current.as_ref() {
+            current = ifaddr.ifa_next;
+
+            let addr = ifaddr.ifa_addr;
+            if addr.is_null() || CStr::from_ptr(ifaddr.ifa_name).to_bytes() != name.as_bytes() {
+                continue;
+            }
+
+            match i32::from((*addr).sa_family) {
+                libc::AF_INET => {
+                    let addr = &*(addr as *const libc::sockaddr_in);
+                    let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                    addresses.push(IpAddr::V4(ip));
+                }
+                libc::AF_INET6 => {
+                    let addr = &*(addr as *const libc::sockaddr_in6);
+                    addresses.push(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)));
+                }
+                _ => {}
+            }
+        }
+
+        libc::freeifaddrs(ifaddrs);
+    }
+
+    addresses
+}
+
+/// Format a rate in bytes per second, or bits per second if `NET_RATE_BITS` is set.
+fn human_rate(bytes_per_second: f64) -> String {
+    if NET_RATE_BITS {
+        human_units(bytes_per_second * 8.0, 1000.0) + "b"
+    } else {
+        human_units(bytes_per_second, 1024.0) + "B"
+    }
+}
+
+/// Format a number of bytes with a binary unit prefix, such as `1.5M`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::human_bytes;
+///
+/// assert_eq!(human_bytes(512.0), "512");
+/// assert_eq!(human_bytes(1536.0), "1.5K");
+/// assert_eq!(human_bytes(20.0 * 1024.0 * 1024.0), "20M");
+/// ```
+pub fn human_bytes(bytes: f64) -> String {
+    human_units(bytes, 1024.0)
+}
+
+/// Format a number with a unit prefix, using `base` 1000 or 1024.
+/// Small numbers get one decimal.
+fn human_units(value: f64, base: f64) -> String {
+    let prefixes = ["", "K", "M", "G", "T", "P"];
+    let mut value = value;
+    let mut prefix = 0;
+
+    while value >= base && prefix < prefixes.len() - 1 {
+        value /= base;
+        prefix += 1;
+    }
+
+    if prefix > 0 && value < 10.0 {
+        format!("{:.1}{}", value, prefixes[prefix])
+    } else {
+        format!("{:.0}{}", value, prefixes[prefix])
+    }
+}
+
+/// Format a duration with its largest unit, such as `5m` or `2h`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::human_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(human_duration(Duration::from_secs(42)), "42s");
+/// assert_eq!(human_duration(Duration::from_secs(150)), "2m");
+/// assert_eq!(human_duration(Duration::from_secs(7300)), "2h");
+/// assert_eq!(human_duration(Duration::from_secs(200000)), "2d");
+/// ```
+pub fn human_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// Icon for a kind of network interface.
+fn net_icon(kind: NetKind) -> &'static str {
+    match kind {
+        NetKind::Wireless => WL_IND,
+        NetKind::Vpn => VPN_IND,
+        _ => ETH_IND,
+    }
+}
+
+/// Search a i3 tree for a node with a certain name or id.
+fn get_node_from_name_or_id(node: Node, name: &str, id: i64) -> Option<Node> {
+    let t = String::new();
+    let node_name = node.name.as_ref().unwrap_or(&t);
+
+    if node.id == id || node_name == name {
+        return Some(node);
+    }
+
+    for n in node.nodes {
+        if let Some(res) = get_node_from_name_or_id(n, name, id) {
+            return Some(res);
+        }
+    }
+
+    None
+}
+
+/// Get all workspaces in a i3 tree.
+fn get_workspaces(data: &mut Vec<Node>, node: Node) {
+    if node.nodetype == NodeType::Workspace {
+        data.push(node);
+    } else {
+        for n in node.nodes {
+            get_workspaces(data, n);
+        }
+    }
+}
+
+/// Put all nodes in a i3 tree `node` into the vector `data`.
+fn get_nodes(data: &mut Vec<Node>, node: Node) {
+    if node.nodes.is_empty()
+        && node.floating_nodes.is_empty()
+        && node.nodetype != NodeType::Workspace
+    {
+        data.push(node);
+    } else {
+        for n in node.nodes {
+            get_nodes(data, n);
+        }
+        for n in node.floating_nodes {
+            get_nodes(data, n);
+        }
+    }
+}
+
+/// Pick the color for `value` from a table of `(color, threshold)` pairs
+/// sorted by threshold, such as `BAT_RANGES`.
+fn range_color<'a>(ranges: &[(&'a str, u32)], value: u32) -> &'a str {
+    ranges
+        .iter()
+        .rev()
+        .find(|(_, threshold)| value >= *threshold)
+        .or_else(|| ranges.first())
+        .map(|(color, _)| *color)
+        .unwrap_or(TEXT_COLOR)
+}
+
+/// Helper function for changing colors on the bar.
+pub fn paint(string: &str, color: &str, layer: &str) -> String {
+    let mut to_paint = String::from(string);
+
+    let default_color = if layer == "F" {
+        TEXT_COLOR
+    } else {
+        if layer == "U" {
             to_paint = format!("%{{+u}}{}%{{-u}}", to_paint);
         }
         BACKGROUND
@@ -589,7 +4717,43 @@ pub fn paint(string: &str, color: &str, layer: &str) -> String {
     )
 }
 
+/// Helper function for showing text from outside the bar, such as a
+/// notification summary, as is instead of as lemonbar formatting.
+///
+/// # Examples
+///
+/// ```
+/// use ba::escape;
+///
+/// let summary = "%{A:exec rm -rf ~:}Click me%{A}";
+/// assert_eq!(escape(summary), "%%{A:exec rm -rf ~:}Click me%%{A}");
+/// assert_eq!(escape("100% done"), "100%% done");
+/// ```
+pub fn escape(string: &str) -> String {
+    string.replace('%', "%%")
+}
+
 /// Helper function for making lemonbar buttons.
 pub fn buttonize(string: &str, command: &str) -> String {
-    format!("%{{A:{1}:}}{0}%{{A}}", string, command)
+    buttonize_on(string, Button::Left, command)
+}
+
+/// Helper function for making lemonbar buttons reacting to a certain
+/// mouse button. Buttons can be nested, the innermost one is closed first.
+///
+/// # Examples
+///
+/// ```
+/// use ba::{Button, buttonize, buttonize_on};
+///
+/// let inner = buttonize(" 1 ", "workspace 1:web");
+/// assert_eq!(inner, "%{A1:workspace 1\\:web:} 1 %{A}");
+///
+/// let outer = buttonize_on(&inner, Button::ScrollUp, "workspace prev");
+/// assert_eq!(outer, "%{A4:workspace prev:}%{A1:workspace 1\\:web:} 1 %{A}%{A}");
+/// ```
+pub fn buttonize_on(string: &str, button: Button, command: &str) -> String {
+    // Colons end the command, so they have to be escaped
+    let command = command.replace(':', "\\:");
+    format!("%{{A{}:{}:}}{}%{{A}}", button as u8, command, string)
 }
@@ -22,8 +22,32 @@ use i3ipc::I3EventListener;
 use i3ipc::Subscription;
 use i3ipc::event::Event;
 
-use std::env::args;
+extern crate chrono;
+use chrono::Local;
+
+#[macro_use]
+extern crate log;
+use log::{LevelFilter, Log, Metadata, Record};
+
+extern crate libc;
+
+extern crate signal_hook;
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+use signal_hook::low_level::raise;
+
+use std::env::{args, current_exe, var};
+use std::fs::{create_dir_all, read_to_string, remove_file, write, OpenOptions};
 use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::process::CommandExt;
+use std::net::Shutdown;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::panic;
+use std::path::PathBuf;
+use std::process::{exit, id, Child, Command, Stdio};
+use std::sync::mpsc::channel;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time;
@@ -35,11 +59,112 @@ const TOGGLE_COMMAND: &str = "nop #toggle_bar_detail";
 const DETAIL_COMMAND: &str = "nop #show_bar_detail";
 const HIDE_DETAIL_COMMAND: &str = "nop #hide_bar_detail";
 
+// Lemonbar, started by `ba run`. Colors are BACKGROUND and TEXT_COLOR.
+const LB_COMMAND: &str = "lemonbar";
+const LB_GEOMETRY: &str = "";
+const LB_UNDERLINE: u32 = 3;
+const LB_FONTS: [(&str, i32); 4] = [
+    ("Liberation Mono For Powerline:style=Bold", 1),
+    ("Font Awesome 5 Free:size=11:style=Solid", -2),
+    ("Font Awesome 5 Brands:size=11", -2),
+    ("Symbola:size=11", 0),
+];
+const LB_RESTART_DELAY: u64 = 1;
+// Longest wait between attempts when lemonbar fails to start
+const LB_MAX_RESTART_DELAY: u64 = 60;
+
+// Answers to messages sent to the running bar
+const CONTROL_ACCEPTED: &str = "ok";
+const CONTROL_REJECTED: &str = "rejected";
+// How long a connection to the running bar may wait between messages
+const CONTROL_TIMEOUT: u64 = 5;
+
+// Printed by `ba timer` without a message
+const TIMER_USAGE: &str = "Usage: ba timer start [LENGTH]|pause|toggle|reset|stopwatch|pomodoro";
+
+// How long `--replace` waits for the running bar to quit
+const REPLACE_TIMEOUT: u64 = 5;
+
+// How long to wait before reconnecting to i3
+const I3_RECONNECT_DELAY: u64 = 1;
+
+/// Lemonbar started by `ba run`, kept so that it can be stopped on shutdown.
+type Lemonbar = Arc<Mutex<Option<Child>>>;
+
+/// Writes log messages to stderr or to the log file.
+struct Logger {
+    output: Mutex<Box<dyn Write + Send>>,
+    level: LevelFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(output, "{} {:<5} {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            record.level(),
+            record.args());
+    }
+
+    fn flush(&self) {
+        let _ = self.output.lock().unwrap_or_else(|e| e.into_inner()).flush();
+    }
+}
+
+/// Path to the log file, `$XDG_STATE_HOME/ba/ba.log`.
+fn log_file_path() -> PathBuf {
+    state_dir().join("ba.log")
+}
+
+/// Set up logging to stderr, or to the log file if `to_file` is set.
+/// Panics are logged as well, since stderr might not go anywhere,
+/// and still reported by the default hook.
+fn init_logging(to_file: bool, debug: bool) {
+    let path = log_file_path();
+    let file = if to_file {
+        path.parent()
+            .map(create_dir_all)
+            .transpose()
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+            .ok()
+    } else {
+        None
+    };
+
+    let output: Box<dyn Write + Send> = match file {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stderr()),
+    };
+
+    let level = if debug { LevelFilter::Debug } else { LevelFilter::Info };
+    let logger = Logger { output: Mutex::new(output), level };
+
+    log::set_boxed_logger(Box::new(logger)).expect("Failed to set up logging");
+    log::set_max_level(level);
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        error!("{}", info);
+        default_hook(info);
+    }));
+}
+
 /// Send messages to i3.
 /// Once called, the program will accept messages through stdin and
 /// then send the messages directly to i3 until the program is closed.
 /// Used to create clickable buttons.
 ///
+/// Messages starting with `ba ` are meant for the bar itself
+/// and are sent to the running bar instead of i3.
+///
 /// # Examples
 ///
 /// ```sh
@@ -53,7 +178,343 @@ pub fn send_messages() {
         io::stdin()
             .read_line(&mut buffer)
             .expect("Failed to read line");
-        connection.run_command(&buffer).expect("I3 command failed");
+
+        if let Some(message) = buffer.strip_prefix("ba ") {
+            if let Err(e) = send_control(message) {
+                warn!("Failed to send '{}' to the bar: {}", message.trim(), e);
+            }
+        } else {
+            run_i3_command(&mut connection, &buffer);
+        }
+    }
+}
+
+/// Run a command in i3, logging it if it fails.
+fn run_i3_command(connection: &mut I3Connection, command: &str) {
+    match connection.run_command(command) {
+        Ok(reply) => {
+            for outcome in reply.outcomes.iter().filter(|o| !o.success) {
+                warn!("i3 rejected '{}': {}",
+                    command.trim(), outcome.error.as_deref().unwrap_or("unknown error"));
+            }
+        }
+        Err(e) => {
+            warn!("Failed to send '{}' to i3, reconnecting: {}", command.trim(), e);
+            match I3Connection::connect() {
+                Ok(new_connection) => *connection = new_connection,
+                Err(e) => warn!("Failed to reconnect to i3: {}", e),
+            }
+        }
+    }
+}
+
+/// Path to the file storing the pid of `ba run`.
+fn pid_file_path() -> PathBuf {
+    let dir = var("XDG_RUNTIME_DIR").unwrap_or_else(|_| String::from("/tmp"));
+    let user = var("USER").unwrap_or_default();
+    PathBuf::from(dir).join(format!("ba-{}.pid", user))
+}
+
+/// Address of the socket the running bar listens for messages on.
+///
+/// The socket is abstract, so it goes away with the bar. Only one
+/// bar can bind it at a time, which keeps a second bar from starting.
+fn control_socket_addr() -> SocketAddr {
+    let user = var("USER").unwrap_or_default();
+    SocketAddr::from_abstract_name(format!("ba-{}", user)).unwrap()
+}
+
+/// Send a message to the running bar, failing if the bar rejects it.
+fn send_control(message: &str) -> io::Result<()> {
+    let mut stream = UnixStream::connect_addr(&control_socket_addr())?;
+    writeln!(stream, "{}", message.trim())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    // A bar that is quitting might not answer
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    if reply.trim() == CONTROL_REJECTED {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the bar rejected the message"));
+    }
+
+    Ok(())
+}
+
+/// Take the control socket, making this the only running bar.
+///
+/// If `replace` is set, the bar already running is asked to quit.
+/// Otherwise the program exits if a bar is already running.
+fn bind_control(replace: bool) -> UnixListener {
+    let addr = control_socket_addr();
+
+    if let Ok(listener) = UnixListener::bind_addr(&addr) {
+        return listener;
+    }
+
+    if !replace {
+        error!("ba is already running, use --replace to replace it");
+        exit(1);
+    }
+
+    info!("Replacing the running bar");
+    let _ = send_control("quit");
+    let poll_time = time::Duration::from_millis(100);
+    let deadline = time::Instant::now() + time::Duration::from_secs(REPLACE_TIMEOUT);
+
+    while time::Instant::now() < deadline {
+        thread::sleep(poll_time);
+        if let Ok(listener) = UnixListener::bind_addr(&addr) {
+            return listener;
+        }
+    }
+
+    error!("The running bar did not quit");
+    exit(1);
+}
+
+/// Listen for messages sent by `send_control` and apply them to the bar.
+/// Each connection is answered on its own thread, so that a client that
+/// never sends anything doesn't keep the others waiting.
+fn listen_control(listener: UnixListener, bar: Arc<Mutex<Bar>>) {
+    for stream in listener.incoming().flatten() {
+        let bar = bar.clone();
+        thread::spawn(move || answer_control(stream, &bar));
+    }
+}
+
+/// Apply the messages of a connection, answering whether each was accepted.
+fn answer_control(mut stream: UnixStream, bar: &Mutex<Bar>) {
+    let timeout = stream.set_read_timeout(Some(time::Duration::from_secs(CONTROL_TIMEOUT)));
+    let lines = match timeout.and_then(|_| stream.try_clone()) {
+        Ok(reader) => BufReader::new(reader).lines(),
+        Err(_) => return,
+    };
+
+    for line in lines.map_while(Result::ok) {
+        let reply = if handle_control(bar, &line) { CONTROL_ACCEPTED } else { CONTROL_REJECTED };
+        let _ = writeln!(stream, "{}", reply);
+    }
+}
+
+/// Apply a message meant for the bar and redraw it.
+///
+/// Supported messages:
+/// * `detail` toggles detail mode for the whole bar
+/// * `detail <module>` toggles detail mode for a single module
+/// * `quit` shuts the bar down
+/// * `<module> <message>` passes a message to a module, such as `volume up`
+///
+/// Returns `false` if the message was rejected.
+fn handle_control(bar: &Mutex<Bar>, message: &str) -> bool {
+    let mut words = message.split_whitespace();
+
+    if message.trim() == "quit" {
+        // Shut down the same way as when the bar is killed
+        let _ = raise(SIGTERM);
+        return true;
+    }
+
+    let mut bar = bar.lock().unwrap();
+
+    match (words.next(), words.next()) {
+        (Some("detail"), None) => {
+            let detailed = bar.is_detailed();
+            bar.set_detailed(!detailed);
+        }
+        (Some("detail"), Some(name)) => {
+            if !bar.toggle_module(name) {
+                warn!("Rejected '{}': there is no module called {}", message, name);
+                return false;
+            }
+        }
+        (Some(name), Some(_)) => {
+            let (_, module_message) = message.trim().split_once(' ').unwrap_or_default();
+            if !bar.send_module(name, module_message.trim()) {
+                warn!("Rejected '{}': there is no module called {} that takes messages", message, name);
+                return false;
+            }
+        }
+        _ => {
+            warn!("Rejected unknown message '{}'", message);
+            return false;
+        }
+    }
+
+    bar.output_data();
+    true
+}
+
+/// Handle signals sent to the bar.
+///
+/// * `SIGTERM` and `SIGINT` shut the bar down
+/// * `SIGUSR1` toggles detail mode
+/// * `SIGUSR2` goes back to compact mode
+fn handle_signals(mut signals: Signals, bar: Arc<Mutex<Bar>>, lemonbar: Lemonbar) {
+    for signal in signals.forever() {
+        match signal {
+            SIGUSR1 => {
+                handle_control(&bar, "detail");
+            }
+            SIGUSR2 => {
+                let mut bar = bar.lock().unwrap();
+                bar.set_detailed(false);
+                bar.output_data();
+            }
+            _ => shutdown(&bar, &lemonbar),
+        }
+    }
+}
+
+/// Stop lemonbar, clean up after the bar and exit.
+fn shutdown(bar: &Mutex<Bar>, lemonbar: &Mutex<Option<Child>>) -> ! {
+    // Hold the bar so that no other thread is in the middle of using it
+    let _bar = bar.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(mut child) = lemonbar.lock().unwrap().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    // The pid file belongs to whichever bar wrote it last
+    let pid = read_to_string(pid_file_path()).unwrap_or_default();
+    if pid.trim() == id().to_string() {
+        let _ = remove_file(pid_file_path());
+    }
+
+    info!("Shutting down");
+    log::logger().flush();
+    exit(0);
+}
+
+/// Start lemonbar with the bar as input and handle its clicks.
+/// Lemonbar is started again if it exits, waiting longer and longer
+/// between attempts while it fails to start.
+fn supervise_lemonbar(bar: Arc<Mutex<Bar>>, lemonbar: Lemonbar) {
+    let mut i3 = I3Connection::connect().expect("Failed to connect to i3");
+    let mut delay = LB_RESTART_DELAY;
+
+    loop {
+        let spawned = Command::new(LB_COMMAND)
+            .args(lemonbar_args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start lemonbar, retrying in {}s: {}", delay, e);
+                thread::sleep(time::Duration::from_secs(delay));
+                delay = (delay * 2).min(LB_MAX_RESTART_DELAY);
+                continue;
+            }
+        };
+        delay = LB_RESTART_DELAY;
+
+        {
+            let mut bar = bar.lock().unwrap();
+            bar.set_output(Box::new(child.stdin.take().unwrap()));
+            bar.output_data();
+        }
+
+        let clicks = BufReader::new(child.stdout.take().unwrap());
+        *lemonbar.lock().unwrap() = Some(child);
+
+        // Lemonbar prints the command of a button when it is clicked
+        for line in clicks.lines().map_while(Result::ok) {
+            if let Some(message) = line.strip_prefix("ba ") {
+                handle_control(&bar, message);
+            } else {
+                run_i3_command(&mut i3, &line);
+            }
+        }
+
+        // Lemonbar is gone when the bar is shutting down
+        match lemonbar.lock().unwrap().take() {
+            Some(mut child) => { let _ = child.wait(); }
+            None => return,
+        }
+
+        warn!("lemonbar exited, restarting it");
+        thread::sleep(time::Duration::from_secs(LB_RESTART_DELAY));
+    }
+}
+
+/// Arguments for lemonbar, matching the colors used by the modules.
+fn lemonbar_args() -> Vec<String> {
+    // Permanent, as with the old togglebar, so the bar stays up if stdin closes
+    let mut lb_args = vec![
+        String::from("-p"),
+        String::from("-B"), String::from(BACKGROUND),
+        String::from("-F"), String::from(TEXT_COLOR),
+        String::from("-u"), LB_UNDERLINE.to_string(),
+    ];
+
+    if !LB_GEOMETRY.is_empty() {
+        lb_args.push(String::from("-g"));
+        lb_args.push(String::from(LB_GEOMETRY));
+    }
+
+    for (font, offset) in LB_FONTS.iter() {
+        lb_args.push(String::from("-o"));
+        lb_args.push(offset.to_string());
+        lb_args.push(String::from("-f"));
+        lb_args.push(String::from(*font));
+    }
+
+    lb_args
+}
+
+/// Return the pid of the running `ba run`, if there is one.
+fn running_pid() -> Option<u32> {
+    let pid: u32 = read_to_string(pid_file_path()).ok()?.trim().parse().ok()?;
+
+    // The pid file might be left over from a bar that has died
+    let comm = read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    if comm.trim() == "ba" && pid != id() {
+        Some(pid)
+    } else {
+        None
+    }
+}
+
+/// Send a message such as `start 25m` to the timer of the running bar.
+fn send_timer(message: &[String]) {
+    if message.is_empty() {
+        eprintln!("{}", TIMER_USAGE);
+        exit(2);
+    }
+
+    if let Err(e) = send_control(&format!("timer {}", message.join(" "))) {
+        eprintln!("Failed to send to the bar: {}", e);
+        exit(1);
+    }
+}
+
+/// Stop the running bar, if there is one.
+fn stop() {
+    if let Some(pid) = running_pid() {
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+    }
+    let _ = remove_file(pid_file_path());
+}
+
+/// Stop the running bar, or start one in the background if none is running.
+#[allow(clippy::zombie_processes)] // The bar keeps running after we exit
+fn toggle() {
+    if running_pid().is_some() {
+        stop();
+    } else {
+        // In its own process group, so that it isn't stopped along with
+        // the terminal or script toggling it
+        Command::new(current_exe().expect("Failed to find the ba executable"))
+            .arg("run")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .expect("Failed to start bar");
     }
 }
 
@@ -61,69 +522,188 @@ pub fn send_messages() {
 /// It constructs the bar and then launches a thread that updates
 /// the bar regularly. The program then waits for events from i3
 /// which should update the bar.
+///
+/// # Usage
+///
+/// * `ba` prints the bar to stdout
+/// * `ba --send` sends clicks from lemonbar to i3, see `send_messages`
+/// * `ba run` starts and supervises lemonbar itself
+/// * `ba toggle` starts `ba run` in the background, or stops it if running
+/// * `ba stop` stops `ba run`
+/// * `ba calendar` prints a calendar and today's events
+/// * `ba timer start|pause|reset` controls the timer of the running bar
+///
+/// Only one bar runs at a time. Pass `--replace` to replace the running bar.
+///
+/// Logs of the bar go to `$XDG_STATE_HOME/ba/ba.log`, or to stderr with
+/// `--log-stderr`. The other commands, and `--send`, log to stderr.
+/// With `--debug`, the time each module takes is logged as well.
 fn main() {
 
     let argv: Vec<String> = args().collect();
+    let command = argv.get(1).map(String::as_str);
+
+    // Commands that are done in a moment tell about problems where they are run
+    let one_shot = argv.contains(&String::from("--send"))
+        || matches!(command, Some("toggle") | Some("stop") | Some("calendar") | Some("timer"));
+
+    init_logging(
+        !one_shot && !argv.contains(&String::from("--log-stderr")),
+        argv.contains(&String::from("--debug")),
+    );
+
+    // Names of weekdays and months in the time module and the calendar
+    set_time_locale("");
 
     if argv.contains(&String::from("--send")) {
         send_messages();
     }
 
+    match command {
+        Some("toggle") => return toggle(),
+        Some("stop") => return stop(),
+        Some("calendar") => return println!("{}", calendar()),
+        Some("timer") => return send_timer(&argv[2..]),
+        _ => {}
+    }
+
+    // Caught from here on, so that a `quit` sent as soon as the control
+    // socket is bound waits for the handler instead of killing the bar
+    let signals = Signals::new([SIGTERM, SIGINT, SIGUSR1, SIGUSR2])
+        .expect("Failed to set up signal handlers");
+
+    let control = bind_control(argv.contains(&String::from("--replace")));
+
+    if command == Some("run") {
+        write(pid_file_path(), id().to_string()).expect("Failed to write pid file");
+    }
+
     // Initialize modules
-    let workspaces = barfn!(workspaces);
-    let time = barfn!(time);
+    let workspaces = barfn!(workspaces,
+        Button::ScrollUp => "workspace prev_on_output",
+        Button::ScrollDown => "workspace next_on_output");
+    let time = barfn!(time, messages,
+        Button::Left => "ba time calendar",
+        Button::Middle => "ba time next");
     let network = barfn!(network);
+    let vpn = barfn!(vpn);
     let battery = barfn!(battery);
     let music = barfn!(music);
     let cpu = barfn!(cpu);
+    let memory = barfn!(memory);
+    let temperature = barfn!(temperature);
+    let disk = barfn!(disk);
+    let pressure = barfn!(pressure);
+    let volume = barfn!(volume, messages,
+        Button::Left => "ba volume mute",
+        Button::ScrollUp => "ba volume up",
+        Button::ScrollDown => "ba volume down");
+    let systemd = barfn!(systemd);
+    let notifications = barfn!(notifications, messages, Button::Left => "ba notifications dnd");
+    let mail = barfn!(mail, messages, Button::Left => "ba mail open");
+    let timer = barfn!(timer, messages,
+        Button::Left => "ba timer toggle",
+        Button::Middle => "ba timer reset");
+    let keyboard = barfn!(keyboard, messages, Button::Left => "ba keyboard next");
+    let backlight = barfn!(backlight, messages,
+        Button::ScrollUp => "ba backlight up",
+        Button::ScrollDown => "ba backlight down");
 
     // Arrange modules
-    let bar = Bar::new(
+    let mut bar = Bar::new(
         vec![workspaces],
         vec![time],
-        vec![music, cpu, network, battery]
+        vec![music, systemd, notifications, mail, timer, keyboard, volume, backlight, pressure, cpu, memory, temperature, disk, vpn, network, battery]
     );
+    bar.set_debug(argv.contains(&String::from("--debug")));
 
     // Prepare bar for multithreading
     let bar_loop = Arc::new(Mutex::new(bar));
     let bar_i3 = bar_loop.clone();
+    let bar_control = bar_loop.clone();
+    let bar_signals = bar_loop.clone();
+    let lemonbar: Lemonbar = Arc::new(Mutex::new(None));
+    let lemonbar_signals = lemonbar.clone();
+
+    // Shut down cleanly when asked to
+    thread::spawn(move || handle_signals(signals, bar_signals, lemonbar_signals));
+
+    // Listen for clicks and other messages sent to the bar
+    thread::spawn(move || listen_control(control, bar_control));
+
+    // Let ba run lemonbar instead of printing to stdout
+    if command == Some("run") {
+        let bar_lemonbar = bar_loop.clone();
+        thread::spawn(move || supervise_lemonbar(bar_lemonbar, lemonbar));
+    }
+
+    // Spawn a thread that updates bar every 2 seconds,
+    // or sooner when a module asks for it
+    let (redraw_sender, redraw) = channel();
+    set_redraw_sender(redraw_sender);
 
-    // Spawn a thread that updates bar every 2 seconds
     thread::spawn(move || {
         let sleep_time = time::Duration::from_secs(UPDATE_FREQ);
 
         loop {
             bar_loop.lock().unwrap().output_data();
-            thread::sleep(sleep_time);
+            let _ = redraw.recv_timeout(sleep_time);
+
+            // Several changes at once only need one redraw
+            while redraw.try_recv().is_ok() {}
         }
     });
 
-    // Set up i3 listener
-    let mut listener = I3EventListener::connect().unwrap();
-    listener.subscribe(&[Subscription::Workspace, Subscription::Binding]).unwrap();
-
-    for event in listener.listen() {
-        match event.unwrap() {
-            Event::WorkspaceEvent(_) => bar_i3.lock().unwrap().output_data(),
-            Event::BindingEvent(e) => {
-                let mut bar_i3 = bar_i3.lock().unwrap();
-                if e.binding.command == TOGGLE_COMMAND {
-                    if bar_i3.is_detailed() {
-                        bar_i3.set_detailed(false);
-                    } else {
+    // Set up i3 listener, reconnecting if i3 goes away
+    loop {
+        let mut listener = match I3EventListener::connect() {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("Failed to connect to i3: {}", e);
+                thread::sleep(time::Duration::from_secs(I3_RECONNECT_DELAY));
+                continue;
+            }
+        };
+        if let Err(e) = listener.subscribe(&[Subscription::Workspace, Subscription::Binding]) {
+            warn!("Failed to subscribe to i3 events: {}", e);
+            thread::sleep(time::Duration::from_secs(I3_RECONNECT_DELAY));
+            continue;
+        }
+        info!("Listening for i3 events");
+
+        for event in listener.listen() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("Lost connection to i3: {}", e);
+                    break;
+                }
+            };
+
+            match event {
+                Event::WorkspaceEvent(_) => bar_i3.lock().unwrap().output_data(),
+                Event::BindingEvent(e) => {
+                    let mut bar_i3 = bar_i3.lock().unwrap();
+                    if e.binding.command == TOGGLE_COMMAND {
+                        if bar_i3.is_detailed() {
+                            bar_i3.set_detailed(false);
+                        } else {
+                            bar_i3.set_detailed(true);
+                        }
+                    } else if e.binding.command == DETAIL_COMMAND {
+                        if bar_i3.is_detailed() { continue; }
                         bar_i3.set_detailed(true);
+                    } else if e.binding.command == HIDE_DETAIL_COMMAND {
+                        if !bar_i3.is_detailed() { continue; }
+                        bar_i3.set_detailed(false);
                     }
-                } else if e.binding.command == DETAIL_COMMAND {
-                    if bar_i3.is_detailed() { continue; }
-                    bar_i3.set_detailed(true);
-                } else if e.binding.command == HIDE_DETAIL_COMMAND {
-                    if !bar_i3.is_detailed() { continue; }
-                    bar_i3.set_detailed(false);
-                }
 
-                bar_i3.output_data()
-            },
-            _ => unreachable!()
+                    bar_i3.output_data()
+                },
+                _ => unreachable!()
+            }
         }
+
+        thread::sleep(time::Duration::from_secs(I3_RECONNECT_DELAY));
     }
 }
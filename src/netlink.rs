@@ -0,0 +1,372 @@
+//! Just enough generic netlink to ask the kernel about
+//! wireless and WireGuard interfaces.
+
+use std::convert::TryInto;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// nl80211, see linux/nl80211.h
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_SSID: u16 = 52;
+
+// WireGuard, see linux/wireguard.h
+const WG_CMD_GET_DEVICE: u8 = 0;
+const WGDEVICE_A_IFNAME: u16 = 2;
+const WGDEVICE_A_PEERS: u16 = 8;
+const WGPEER_A_LAST_HANDSHAKE_TIME: u16 = 6;
+
+// Sizes of struct nlmsghdr, struct genlmsghdr and struct nlattr
+const NLMSG_HDRLEN: usize = 16;
+const GENL_HDRLEN: usize = 4;
+const NLA_HDRLEN: usize = 4;
+
+const RECV_BUFFER: usize = 32768;
+
+/// A netlink attribute, its type and payload.
+pub type Attribute = (u16, Vec<u8>);
+
+/// A generic netlink socket.
+pub struct Socket {
+    fd: OwnedFd,
+}
+
+impl Socket {
+    /// Open a new generic netlink socket.
+    pub fn new() -> io::Result<Socket> {
+        let fd = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_GENERIC)
+        };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Socket { fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+    }
+
+    /// Look up the id of a generic netlink family, such as `nl80211`.
+    pub fn family_id(&self, name: &str) -> io::Result<u16> {
+        let name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let replies = self.request(
+            libc::GENL_ID_CTRL as u16,
+            libc::CTRL_CMD_GETFAMILY as u8,
+            0,
+            &[(libc::CTRL_ATTR_FAMILY_NAME as u16, name.as_bytes_with_nul())],
+        )?;
+
+        replies
+            .iter()
+            .flatten()
+            .find(|(kind, _)| *kind == libc::CTRL_ATTR_FAMILY_ID as u16)
+            .and_then(|(_, payload)| read_u16(payload))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "family id missing"))
+    }
+
+    /// Send a request and collect the attributes of every reply.
+    /// `flags` is added to `NLM_F_REQUEST`, for example `NLM_F_DUMP`.
+    pub fn request(&self, family: u16, command: u8, flags: u16, attributes: &[(u16, &[u8])])
+        -> io::Result<Vec<Vec<Attribute>>>
+    {
+        self.send(family, command, flags, attributes)?;
+        self.receive()
+    }
+
+    fn send(&self, family: u16, command: u8, flags: u16, attributes: &[(u16, &[u8])])
+        -> io::Result<()>
+    {
+        let message = encode_request(family, command, flags, attributes);
+
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+
+        if sent < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn receive(&self) -> io::Result<Vec<Vec<Attribute>>> {
+        let mut replies = Vec::new();
+        let mut buffer = vec![0u8; RECV_BUFFER];
+
+        loop {
+            let received = unsafe {
+                libc::recv(self.fd.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0)
+            };
+            if received < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if !parse_messages(&buffer[..received as usize], &mut replies)? {
+                return Ok(replies);
+            }
+        }
+    }
+}
+
+/// Build a generic netlink request, the message `Socket::request` sends.
+///
+/// # Examples
+///
+/// ```
+/// use ba::netlink::encode_request;
+///
+/// let message = encode_request(0x10, 3, 0, &[(2, b"wireguard\0")]);
+///
+/// // The header, the generic netlink header and an attribute padded to 4 bytes
+/// assert_eq!(message.len(), 16 + 4 + 16);
+/// assert_eq!(message[..4], 36u32.to_ne_bytes());
+/// assert_eq!(message[4..6], 0x10u16.to_ne_bytes());
+/// assert_eq!(message[6..8], (libc::NLM_F_REQUEST as u16).to_ne_bytes());
+/// assert_eq!(message[16..20], [3, 1, 0, 0]);
+/// assert_eq!(message[20..22], 14u16.to_ne_bytes());
+/// assert_eq!(message[22..24], 2u16.to_ne_bytes());
+/// assert_eq!(&message[24..], b"wireguard\0\0\0");
+/// ```
+pub fn encode_request(family: u16, command: u8, flags: u16, attributes: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut message = Vec::new();
+
+    // struct nlmsghdr, the length is filled in last
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(&family.to_ne_bytes());
+    message.extend_from_slice(&(libc::NLM_F_REQUEST as u16 | flags).to_ne_bytes());
+    message.extend_from_slice(&1u32.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+
+    // struct genlmsghdr
+    message.extend_from_slice(&[command, 1, 0, 0]);
+
+    for (kind, payload) in attributes {
+        message.extend_from_slice(&((NLA_HDRLEN + payload.len()) as u16).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(payload);
+        message.resize(align(message.len()), 0);
+    }
+
+    let length = message.len() as u32;
+    message[..4].copy_from_slice(&length.to_ne_bytes());
+    message
+}
+
+/// Add the attributes of every generic netlink message in `data` to `replies`,
+/// returning whether more messages are to come, as in a multipart dump.
+/// An error message from the kernel is returned as an error.
+///
+/// # Examples
+///
+/// ```
+/// use ba::netlink::{encode_request, parse_messages};
+///
+/// // A reply looks like a request with other attributes
+/// let mut data = encode_request(0x1c, 7, libc::NLM_F_MULTI as u16, &[(3, &[2, 0, 0, 0])]);
+/// let mut replies = Vec::new();
+/// assert!(parse_messages(&data, &mut replies).unwrap());
+/// assert_eq!(replies, [vec![(3, vec![2, 0, 0, 0])]]);
+///
+/// // The dump ends with NLMSG_DONE, which may be in the same buffer
+/// let done = encode_request(libc::NLMSG_DONE as u16, 0, libc::NLM_F_MULTI as u16, &[]);
+/// data.extend_from_slice(&done);
+/// let mut replies = Vec::new();
+/// assert!(!parse_messages(&data, &mut replies).unwrap());
+/// assert_eq!(replies.len(), 1);
+///
+/// // An error message holds a negative errno after the header
+/// let mut error = encode_request(libc::NLMSG_ERROR as u16, 0, 0, &[]);
+/// error[16..20].copy_from_slice(&(-libc::ENODEV).to_ne_bytes());
+/// let error = parse_messages(&error, &mut Vec::new()).unwrap_err();
+/// assert_eq!(error.raw_os_error(), Some(libc::ENODEV));
+///
+/// // Truncated messages are ignored
+/// assert!(!parse_messages(&data[..20], &mut Vec::new()).unwrap());
+/// ```
+pub fn parse_messages(mut data: &[u8], replies: &mut Vec<Vec<Attribute>>) -> io::Result<bool> {
+    let mut multipart = false;
+
+    while data.len() >= NLMSG_HDRLEN {
+        let length = read_u32(data).unwrap_or(0) as usize;
+        let kind = read_u16(&data[4..]).unwrap_or(0);
+        let flags = read_u16(&data[6..]).unwrap_or(0);
+
+        if length < NLMSG_HDRLEN || length > data.len() {
+            break;
+        }
+
+        match i32::from(kind) {
+            libc::NLMSG_DONE => return Ok(false),
+            libc::NLMSG_ERROR => {
+                // An error code of zero is an acknowledgement
+                let code = read_u32(&data[NLMSG_HDRLEN..]).unwrap_or(0) as i32;
+                if code != 0 {
+                    return Err(io::Error::from_raw_os_error(-code));
+                }
+                return Ok(false);
+            }
+            _ => {
+                let payload = &data[(NLMSG_HDRLEN + GENL_HDRLEN).min(length)..length];
+                replies.push(parse_attributes(payload));
+            }
+        }
+
+        multipart |= flags & libc::NLM_F_MULTI as u16 != 0;
+        data = &data[align(length).min(data.len())..];
+    }
+
+    Ok(multipart)
+}
+
+/// Split a buffer of netlink attributes into its attributes.
+/// Nested attributes can be parsed by calling this on the payload.
+///
+/// # Examples
+///
+/// ```
+/// use ba::netlink::parse_attributes;
+///
+/// // The SSID "home" and an interface index of 2, each after its length and type
+/// let mut data = Vec::new();
+/// data.extend_from_slice(&[&8u16.to_ne_bytes()[..], &52u16.to_ne_bytes(), b"home"].concat());
+/// data.extend_from_slice(&[&8u16.to_ne_bytes()[..], &3u16.to_ne_bytes(), &2u32.to_ne_bytes()].concat());
+/// assert_eq!(parse_attributes(&data), [(52, b"home".to_vec()), (3, vec![2, 0, 0, 0])]);
+///
+/// // Payloads are padded to 4 bytes, and the nested flag is not part of the type
+/// let data = [&5u16.to_ne_bytes()[..], &0x8008u16.to_ne_bytes(), &[1, 0, 0, 0]].concat();
+/// assert_eq!(parse_attributes(&data), [(8, vec![1])]);
+///
+/// // An attribute longer than the buffer ends it
+/// let data = [&9u16.to_ne_bytes()[..], &1u16.to_ne_bytes(), &[1]].concat();
+/// assert!(parse_attributes(&data).is_empty());
+/// ```
+pub fn parse_attributes(mut data: &[u8]) -> Vec<Attribute> {
+    let mut attributes = Vec::new();
+
+    while data.len() >= NLA_HDRLEN {
+        let length = read_u16(data).unwrap_or(0) as usize;
+        let kind = read_u16(&data[2..]).unwrap_or(0) & libc::NLA_TYPE_MASK as u16;
+
+        if length < NLA_HDRLEN || length > data.len() {
+            break;
+        }
+
+        attributes.push((kind, data[NLA_HDRLEN..length].to_vec()));
+        data = &data[align(length).min(data.len())..];
+    }
+
+    attributes
+}
+
+/// Get the SSID of the network a wireless interface is connected to.
+pub fn wireless_ssid(interface: &str) -> io::Result<Option<String>> {
+    let socket = Socket::new()?;
+    let family = socket.family_id("nl80211")?;
+    let index = interface_index(interface)?;
+
+    let replies = socket.request(
+        family,
+        NL80211_CMD_GET_INTERFACE,
+        0,
+        &[(NL80211_ATTR_IFINDEX, &index.to_ne_bytes())],
+    )?;
+
+    Ok(parse_ssid(&replies))
+}
+
+/// Find the SSID in the replies to `NL80211_CMD_GET_INTERFACE`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::netlink::{encode_request, parse_messages, parse_ssid};
+///
+/// let reply = encode_request(0x1c, 7, 0, &[(3, &2u32.to_ne_bytes()), (52, b"caf\xc3\xa9")]);
+/// let mut replies = Vec::new();
+/// parse_messages(&reply, &mut replies).unwrap();
+/// assert_eq!(parse_ssid(&replies).as_deref(), Some("café"));
+///
+/// // An interface that is not connected has no SSID
+/// let reply = encode_request(0x1c, 7, 0, &[(3, &2u32.to_ne_bytes())]);
+/// let mut replies = Vec::new();
+/// parse_messages(&reply, &mut replies).unwrap();
+/// assert_eq!(parse_ssid(&replies), None);
+/// ```
+pub fn parse_ssid(replies: &[Vec<Attribute>]) -> Option<String> {
+    replies
+        .iter()
+        .flatten()
+        .find(|(kind, _)| *kind == NL80211_ATTR_SSID)
+        .map(|(_, ssid)| String::from_utf8_lossy(ssid).into_owned())
+}
+
+/// Get the latest handshake of any peer of a WireGuard interface.
+///
+/// This usually needs `CAP_NET_ADMIN`.
+pub fn wireguard_last_handshake(interface: &str) -> io::Result<Option<SystemTime>> {
+    let socket = Socket::new()?;
+    let family = socket.family_id("wireguard")?;
+    let name = CString::new(interface).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let replies = socket.request(
+        family,
+        WG_CMD_GET_DEVICE,
+        libc::NLM_F_DUMP as u16,
+        &[(WGDEVICE_A_IFNAME, name.as_bytes_with_nul())],
+    )?;
+
+    // Peers are nested attributes, each with its own nested attributes
+    let latest = replies
+        .iter()
+        .flatten()
+        .filter(|(kind, _)| *kind == WGDEVICE_A_PEERS)
+        .flat_map(|(_, peers)| parse_attributes(peers))
+        .flat_map(|(_, peer)| parse_attributes(&peer))
+        .filter(|(kind, _)| *kind == WGPEER_A_LAST_HANDSHAKE_TIME)
+        .filter_map(|(_, time)| read_u64(&time))
+        .max();
+
+    // A handshake time of zero means there has not been one
+    Ok(latest
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)))
+}
+
+/// Get the index of a network interface from its name.
+pub fn interface_index(interface: &str) -> io::Result<u32> {
+    let name = CString::new(interface).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        index => Ok(index),
+    }
+}
+
+fn read_u16(data: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes([*data.first()?, *data.get(1)?]))
+}
+
+fn read_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes([*data.first()?, *data.get(1)?, *data.get(2)?, *data.get(3)?]))
+}
+
+fn read_u64(data: &[u8]) -> Option<u64> {
+    data.get(..8)?.try_into().ok().map(u64::from_ne_bytes)
+}
+
+/// Round up to the 4 byte alignment of netlink messages and attributes.
+fn align(length: usize) -> usize {
+    (length + 3) & !3
+}
@@ -0,0 +1,422 @@
+//! Just enough of the PulseAudio native protocol to read, change and watch
+//! the volume of the default sink. PipeWire speaks it through pipewire-pulse.
+
+use std::convert::TryInto;
+use std::env::var_os;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+// Commands, see pulsecore/native-common.h
+const COMMAND_ERROR: u32 = 0;
+const COMMAND_REPLY: u32 = 2;
+const COMMAND_AUTH: u32 = 8;
+const COMMAND_SET_CLIENT_NAME: u32 = 9;
+const COMMAND_GET_SINK_INFO: u32 = 21;
+const COMMAND_SUBSCRIBE: u32 = 35;
+const COMMAND_SET_SINK_VOLUME: u32 = 36;
+const COMMAND_SET_SINK_MUTE: u32 = 39;
+const COMMAND_SUBSCRIBE_EVENT: u32 = 66;
+
+// The oldest version with everything used here is 13
+const PROTOCOL_VERSION: u32 = 32;
+const COOKIE_LENGTH: usize = 256;
+const CONTROL_CHANNEL: u32 = u32::MAX;
+const INVALID_INDEX: u32 = u32::MAX;
+const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+
+// Changes to the server include a new default sink
+const SUBSCRIPTION_MASK_SINK: u32 = 0x0001;
+const SUBSCRIPTION_MASK_SERVER: u32 = 0x0080;
+
+/// The volume of each channel of a sink, where 100% is `VOLUME_NORM`.
+pub const VOLUME_NORM: u32 = 0x10000;
+
+// Tags of the values in a packet
+const TAG_STRING: u8 = b't';
+const TAG_STRING_NULL: u8 = b'N';
+const TAG_U32: u8 = b'L';
+const TAG_U8: u8 = b'B';
+const TAG_U64: u8 = b'R';
+const TAG_S64: u8 = b'r';
+const TAG_SAMPLE_SPEC: u8 = b'a';
+const TAG_ARBITRARY: u8 = b'x';
+const TAG_BOOLEAN_TRUE: u8 = b'1';
+const TAG_BOOLEAN_FALSE: u8 = b'0';
+const TAG_TIMEVAL: u8 = b'T';
+const TAG_USEC: u8 = b'U';
+const TAG_CHANNEL_MAP: u8 = b'm';
+const TAG_CVOLUME: u8 = b'v';
+const TAG_VOLUME: u8 = b'V';
+const TAG_PROPLIST: u8 = b'P';
+
+/// The volume and mute state of a sink.
+pub struct Sink {
+    pub volumes: Vec<u32>,
+    pub muted: bool,
+}
+
+/// A connection to a PulseAudio server.
+pub struct Connection {
+    stream: UnixStream,
+    tag: u32,
+}
+
+impl Connection {
+    /// Connect to the server in `$PULSE_SERVER`, or the one of this user.
+    pub fn connect() -> io::Result<Connection> {
+        let path = match var_os("PULSE_SERVER") {
+            Some(server) => PathBuf::from(server.to_string_lossy().trim_start_matches("unix:")),
+            None => PathBuf::from(var_os("XDG_RUNTIME_DIR")
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?)
+                .join("pulse/native"),
+        };
+
+        let mut connection = Connection { stream: UnixStream::connect(path)?, tag: 0 };
+
+        // Servers trust clients of the same user on the socket, the cookie
+        // is only needed for other users
+        let mut auth = Packet::new();
+        auth.put_u32(PROTOCOL_VERSION);
+        auth.put_arbitrary(&cookie());
+        connection.request(COMMAND_AUTH, auth)?;
+
+        let mut name = Packet::new();
+        name.put_proplist(&[("application.name", "ba")]);
+        connection.request(COMMAND_SET_CLIENT_NAME, name)?;
+
+        Ok(connection)
+    }
+
+    /// Read the volume and mute state of the default sink.
+    pub fn sink(&mut self) -> io::Result<Sink> {
+        let mut request = Packet::new();
+        request.put_u32(INVALID_INDEX);
+        request.put_string(DEFAULT_SINK);
+
+        parse_sink(&self.request(COMMAND_GET_SINK_INFO, request)?)
+    }
+
+    /// Set the volume of each channel of the default sink.
+    pub fn set_volume(&mut self, volumes: &[u32]) -> io::Result<()> {
+        let mut request = Packet::new();
+        request.put_u32(INVALID_INDEX);
+        request.put_string(DEFAULT_SINK);
+        request.put_cvolume(volumes);
+
+        self.request(COMMAND_SET_SINK_VOLUME, request).map(|_| ())
+    }
+
+    /// Mute or unmute the default sink.
+    pub fn set_mute(&mut self, muted: bool) -> io::Result<()> {
+        let mut request = Packet::new();
+        request.put_u32(INVALID_INDEX);
+        request.put_string(DEFAULT_SINK);
+        request.put_boolean(muted);
+
+        self.request(COMMAND_SET_SINK_MUTE, request).map(|_| ())
+    }
+
+    /// Ask to be told about changes to sinks, see `wait`.
+    pub fn subscribe(&mut self) -> io::Result<()> {
+        let mut request = Packet::new();
+        request.put_u32(SUBSCRIPTION_MASK_SINK | SUBSCRIPTION_MASK_SERVER);
+
+        self.request(COMMAND_SUBSCRIBE, request).map(|_| ())
+    }
+
+    /// Wait until a sink or the server changes.
+    pub fn wait(&mut self) -> io::Result<()> {
+        loop {
+            let packet = self.receive()?;
+            if Reader(&packet).u32()? == COMMAND_SUBSCRIBE_EVENT {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Send a command and return the rest of the reply after its tag.
+    fn request(&mut self, command: u32, packet: Packet) -> io::Result<Vec<u8>> {
+        self.tag = self.tag.wrapping_add(1);
+
+        let mut header = Packet::new();
+        header.put_u32(command);
+        header.put_u32(self.tag);
+        let payload = [header.0, packet.0].concat();
+
+        // Length, channel, offset and flags
+        let mut message = Vec::with_capacity(20 + payload.len());
+        for field in &[payload.len() as u32, CONTROL_CHANNEL, 0, 0, 0] {
+            message.extend_from_slice(&field.to_be_bytes());
+        }
+        message.extend_from_slice(&payload);
+        self.stream.write_all(&message)?;
+
+        // Events may arrive before the reply after subscribing
+        loop {
+            let packet = self.receive()?;
+            let mut reply = Reader(&packet);
+            let (kind, tag) = (reply.u32()?, reply.u32()?);
+
+            match kind {
+                COMMAND_REPLY if tag == self.tag => return Ok(reply.0.to_vec()),
+                COMMAND_ERROR if tag == self.tag => {
+                    let code = reply.u32().unwrap_or_default();
+                    return Err(io::Error::other(format!("server error {}", code)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Receive the next packet on the control channel.
+    fn receive(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            let mut header = [0u8; 20];
+            self.stream.read_exact(&mut header)?;
+
+            let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+            let channel = u32::from_be_bytes(header[4..8].try_into().unwrap());
+
+            let mut packet = vec![0u8; length as usize];
+            self.stream.read_exact(&mut packet)?;
+
+            if channel == CONTROL_CHANNEL {
+                return Ok(packet);
+            }
+        }
+    }
+}
+
+/// Read the cookie the server hands out, or make one up when there is none.
+fn cookie() -> Vec<u8> {
+    let home = PathBuf::from(var_os("HOME").unwrap_or_default());
+    let paths = [
+        var_os("PULSE_COOKIE").map(PathBuf::from),
+        var_os("XDG_CONFIG_HOME").map(|config| PathBuf::from(config).join("pulse/cookie")),
+        Some(home.join(".config/pulse/cookie")),
+        Some(home.join(".pulse-cookie")),
+    ];
+
+    paths
+        .iter()
+        .flatten()
+        .filter_map(|path| fs::read(path).ok())
+        .find(|cookie| cookie.len() == COOKIE_LENGTH)
+        .unwrap_or_else(|| vec![0; COOKIE_LENGTH])
+}
+
+/// Read the volume and mute state from the reply to `COMMAND_GET_SINK_INFO`.
+///
+/// # Examples
+///
+/// ```
+/// use ba::pulse::parse_sink;
+///
+/// let mut reply = Vec::new();
+/// reply.extend_from_slice(b"L\0\0\0\x2a");                   // Index
+/// reply.extend_from_slice(b"talsa_output.pci\0");            // Name
+/// reply.extend_from_slice(b"tBuilt-in Audio\0");             // Description
+/// reply.extend_from_slice(b"a\x03\x02\0\0\xbb\x80");            // s16le, 2 channels, 48000 Hz
+/// reply.extend_from_slice(b"m\x02\x01\x02");                   // Front left and right
+/// reply.extend_from_slice(b"L\xff\xff\xff\xff");               // No owner module
+/// reply.extend_from_slice(b"v\x02\0\x01\0\0\0\0\x80\0");        // 100% and 50%
+/// reply.extend_from_slice(b"1");                            // Muted
+/// reply.extend_from_slice(b"L\0\0\0\0tmonitor\0");            // And more that is not read
+///
+/// let sink = parse_sink(&reply).unwrap();
+/// assert_eq!(sink.volumes, [0x10000, 0x8000]);
+/// assert!(sink.muted);
+///
+/// // A reply cut short is an error, not a guess
+/// assert!(parse_sink(&reply[..reply.len() - 20]).is_err());
+/// ```
+pub fn parse_sink(reply: &[u8]) -> io::Result<Sink> {
+    let mut reply = Reader(reply);
+
+    // Index, name, description, sample spec, channel map and owner module
+    for _ in 0..6 {
+        reply.skip()?;
+    }
+
+    Ok(Sink { volumes: reply.cvolume()?, muted: reply.boolean()? })
+}
+
+/// The values of a packet being built.
+///
+/// # Examples
+///
+/// ```
+/// use ba::pulse::Packet;
+///
+/// let mut packet = Packet::new();
+/// packet.put_u32(0xffff_ffff);
+/// packet.put_string("@DEFAULT_SINK@");
+/// packet.put_cvolume(&[0x10000, 0x8000]);
+/// packet.put_boolean(false);
+/// assert_eq!(packet.0, b"L\xff\xff\xff\xfft@DEFAULT_SINK@\0v\x02\0\x01\0\0\0\0\x80\x000");
+///
+/// // Property values are arbitrary data, with their length before them
+/// let mut packet = Packet::new();
+/// packet.put_proplist(&[("application.name", "ba")]);
+/// assert_eq!(packet.0, b"Ptapplication.name\0L\0\0\0\x03x\0\0\0\x03ba\0N");
+/// ```
+#[derive(Default)]
+pub struct Packet(pub Vec<u8>);
+
+impl Packet {
+    /// Create an empty packet.
+    pub fn new() -> Packet {
+        Packet(Vec::new())
+    }
+
+    /// Add a 32-bit integer.
+    pub fn put_u32(&mut self, value: u32) {
+        self.0.push(TAG_U32);
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Add a string, which must not contain a null byte.
+    pub fn put_string(&mut self, value: &str) {
+        self.0.push(TAG_STRING);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+    }
+
+    /// Add a boolean.
+    pub fn put_boolean(&mut self, value: bool) {
+        self.0.push(if value { TAG_BOOLEAN_TRUE } else { TAG_BOOLEAN_FALSE });
+    }
+
+    /// Add arbitrary data.
+    pub fn put_arbitrary(&mut self, value: &[u8]) {
+        self.0.push(TAG_ARBITRARY);
+        self.0.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.0.extend_from_slice(value);
+    }
+
+    /// Add the volume of each channel.
+    pub fn put_cvolume(&mut self, volumes: &[u32]) {
+        self.0.push(TAG_CVOLUME);
+        self.0.push(volumes.len() as u8);
+        for volume in volumes {
+            self.0.extend_from_slice(&volume.to_be_bytes());
+        }
+    }
+
+    /// Add a property list, where every value is a string.
+    pub fn put_proplist(&mut self, properties: &[(&str, &str)]) {
+        self.0.push(TAG_PROPLIST);
+        for (key, value) in properties {
+            let value = [value.as_bytes(), &[0]].concat();
+            self.put_string(key);
+            self.put_u32(value.len() as u32);
+            self.put_arbitrary(&value);
+        }
+        self.0.push(TAG_STRING_NULL);
+    }
+}
+
+/// The values of a received packet, read from the front.
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Read a 32-bit integer.
+    pub fn u32(&mut self) -> io::Result<u32> {
+        self.tag(TAG_U32)?;
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Read a boolean.
+    pub fn boolean(&mut self) -> io::Result<bool> {
+        match self.take(1)?[0] {
+            TAG_BOOLEAN_TRUE => Ok(true),
+            TAG_BOOLEAN_FALSE => Ok(false),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Read the volume of each channel.
+    pub fn cvolume(&mut self) -> io::Result<Vec<u32>> {
+        self.tag(TAG_CVOLUME)?;
+        let channels = self.take(1)?[0] as usize;
+
+        Ok(self
+            .take(channels * 4)?
+            .chunks_exact(4)
+            .map(|volume| u32::from_be_bytes(volume.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Skip the next value, whatever it is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::pulse::Reader;
+    ///
+    /// let packet = [
+    ///     &b"tname\0N01"[..],                 // Strings, null and booleans
+    ///     b"B\x07L\0\0\0\x01V\0\x01\0\0",         // Integers and a volume
+    ///     b"a\x03\x02\0\0\xbb\x80",                // Sample spec
+    ///     b"R\0\0\0\0\0\0\0\x01U\0\0\0\0\0\0\0\x02", // 64-bit integers and times
+    ///     b"x\0\0\0\x03abc",                   // Arbitrary data
+    ///     b"m\x02\x01\x02v\x01\0\x01\0\0",         // Channel map and volumes
+    ///     b"L\0\0\0\x2a",
+    /// ]
+    /// .concat();
+    ///
+    /// let mut reader = Reader(&packet);
+    /// for _ in 0..13 {
+    ///     reader.skip().unwrap();
+    /// }
+    /// assert_eq!(reader.u32().unwrap(), 42);
+    ///
+    /// // Unknown tags and strings without an end can't be skipped
+    /// assert!(Reader(b"?").skip().is_err());
+    /// assert!(Reader(b"tname").skip().is_err());
+    /// assert!(Reader(b"x\0\0\0\x09abc").skip().is_err());
+    /// ```
+    pub fn skip(&mut self) -> io::Result<()> {
+        let tag = self.take(1)?[0];
+
+        let length = match tag {
+            TAG_STRING => self.0.iter().position(|&b| b == 0).ok_or_else(invalid)? + 1,
+            TAG_STRING_NULL | TAG_BOOLEAN_TRUE | TAG_BOOLEAN_FALSE => 0,
+            TAG_U8 => 1,
+            TAG_U32 | TAG_VOLUME => 4,
+            TAG_SAMPLE_SPEC => 6,
+            TAG_U64 | TAG_S64 | TAG_TIMEVAL | TAG_USEC => 8,
+            TAG_ARBITRARY => u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as usize,
+            TAG_CHANNEL_MAP => self.take(1)?[0] as usize,
+            TAG_CVOLUME => self.take(1)?[0] as usize * 4,
+            _ => return Err(invalid()),
+        };
+
+        self.take(length).map(|_| ())
+    }
+
+    fn tag(&mut self, tag: u8) -> io::Result<()> {
+        if self.take(1)?[0] != tag {
+            return Err(invalid());
+        }
+
+        Ok(())
+    }
+
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(invalid());
+        }
+
+        let data: &'a [u8] = self.0;
+        let (taken, rest) = data.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed packet")
+}
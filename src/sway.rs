@@ -0,0 +1,62 @@
+//! Just enough of the sway IPC protocol to follow the keyboard layout.
+
+use std::env::var_os;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+
+const MAGIC: &[u8] = b"i3-ipc";
+
+pub const RUN_COMMAND: u32 = 0;
+pub const SUBSCRIBE: u32 = 2;
+pub const GET_INPUTS: u32 = 100;
+
+/// A connection to sway.
+pub struct Ipc {
+    stream: UnixStream,
+}
+
+impl Ipc {
+    /// Connect to the sway socket in `$SWAYSOCK`.
+    pub fn connect() -> io::Result<Ipc> {
+        let path = var_os("SWAYSOCK")
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "SWAYSOCK is not set"))?;
+
+        Ok(Ipc { stream: UnixStream::connect(path)? })
+    }
+
+    /// Send a message and return the payload of the reply.
+    pub fn request(&mut self, kind: u32, payload: &str) -> io::Result<String> {
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        self.stream.write_all(&message)?;
+
+        // Events may arrive before the reply after subscribing
+        loop {
+            let (reply_kind, reply) = self.receive()?;
+            if reply_kind == kind {
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Wait for the next message and return its type and payload.
+    /// Events have the highest bit of the type set.
+    pub fn receive(&mut self) -> io::Result<(u32, String)> {
+        let mut header = [0u8; 14];
+        self.stream.read_exact(&mut header)?;
+
+        if &header[..6] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an i3-ipc message"));
+        }
+
+        let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+        let kind = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+
+        let mut payload = vec![0u8; length as usize];
+        self.stream.read_exact(&mut payload)?;
+
+        Ok((kind, String::from_utf8_lossy(&payload).into_owned()))
+    }
+}
@@ -0,0 +1,321 @@
+//! Just enough of the TZif format, and the POSIX `TZ` rules it ends with,
+//! to find the offset from UTC and the abbreviation of a time zone.
+
+use std::convert::TryInto;
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+// Sizes in the header, see tzfile(5)
+const HEADER_SIZE: usize = 44;
+const COUNTS: usize = 20;
+const TYPE_SIZE: usize = 6;
+
+/// An offset from UTC in seconds and its abbreviation.
+pub type LocalTime = (i32, String);
+
+/// A time zone read from a TZif file.
+#[derive(Debug)]
+pub struct TimeZone {
+    /// Times the offset changes at and the index of the new type
+    transitions: Vec<(i64, usize)>,
+    types: Vec<LocalTime>,
+    /// Used after the last transition
+    rule: Option<Rule>,
+}
+
+/// A POSIX `TZ` rule, such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Debug)]
+pub struct Rule {
+    standard: LocalTime,
+    /// The daylight saving time and when, in local time, it starts and ends
+    dst: Option<(LocalTime, RuleDate, RuleDate)>,
+}
+
+/// A day of the year and the time of day in seconds, as in a `TZ` rule.
+#[derive(Debug)]
+enum RuleDate {
+    /// Day 1 to 365, where February 29 is never counted
+    Julian(u32, i32),
+    /// Day 0 to 365, counting February 29
+    Zero(u32, i32),
+    /// Month, week 1 to 5 where 5 is the last, and weekday from Sunday
+    Month(u32, u32, u32, i32),
+}
+
+impl TimeZone {
+    /// Parse version 1 or later of TZif, preferring the 64-bit data.
+    pub fn parse(data: &[u8]) -> Option<TimeZone> {
+        let counts = header(data)?;
+
+        if data[4] == 0 {
+            return parse_data(&data[HEADER_SIZE..], counts, 4, None);
+        }
+
+        // The 64-bit header and data follow the 32-bit data, and then the rule
+        let rest = data.get(HEADER_SIZE + data_size(counts, 4)..)?;
+        let counts = header(rest)?;
+        let footer = rest.get(HEADER_SIZE + data_size(counts, 8)..)?;
+        let rule = std::str::from_utf8(footer.get(1..)?)
+            .ok()?
+            .split('\n')
+            .next()
+            .and_then(Rule::parse);
+
+        parse_data(&rest[HEADER_SIZE..], counts, 8, rule)
+    }
+
+    /// Find the offset and abbreviation at a Unix time.
+    pub fn find(&self, time: i64) -> Option<LocalTime> {
+        let after = self.transitions.partition_point(|(at, _)| *at <= time);
+
+        match (after, &self.rule) {
+            (after, Some(rule)) if after == self.transitions.len() => Some(rule.find(time)),
+            (0, _) => self.types.first().cloned(),
+            (after, _) => self.types.get(self.transitions[after - 1].1).cloned(),
+        }
+    }
+}
+
+/// Read the counts from a TZif header.
+fn header(data: &[u8]) -> Option<[usize; 6]> {
+    if !data.starts_with(b"TZif") || data.len() < HEADER_SIZE {
+        return None;
+    }
+
+    let mut counts = [0; 6];
+    for (i, count) in counts.iter_mut().enumerate() {
+        let at = COUNTS + i * 4;
+        *count = u32::from_be_bytes(data[at..at + 4].try_into().unwrap()) as usize;
+    }
+
+    Some(counts)
+}
+
+/// The size of the data after a header with `counts`, with times of `time_size` bytes.
+fn data_size(counts: [usize; 6], time_size: usize) -> usize {
+    let [utc, std, leap, times, types, chars] = counts;
+    times * (time_size + 1) + types * TYPE_SIZE + chars + leap * (time_size + 4) + std + utc
+}
+
+fn parse_data(data: &[u8], counts: [usize; 6], time_size: usize, rule: Option<Rule>) -> Option<TimeZone> {
+    let [_, _, _, times, types, chars] = counts;
+    if data.len() < data_size(counts, time_size) || types == 0 {
+        return None;
+    }
+
+    let (at, data) = data.split_at(times * time_size);
+    let (indices, data) = data.split_at(times);
+    let (kinds, data) = data.split_at(types * TYPE_SIZE);
+    let names = &data[..chars];
+
+    let at = at.chunks_exact(time_size).map(|time| match time_size {
+        4 => i64::from(i32::from_be_bytes(time.try_into().unwrap())),
+        _ => i64::from_be_bytes(time.try_into().unwrap()),
+    });
+
+    let types = kinds
+        .chunks_exact(TYPE_SIZE)
+        .map(|kind| {
+            let offset = i32::from_be_bytes(kind[0..4].try_into().unwrap());
+            let name = names.get(kind[5] as usize..).unwrap_or_default();
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            (offset, String::from_utf8_lossy(name).into_owned())
+        })
+        .collect::<Vec<_>>();
+
+    let transitions = at
+        .zip(indices.iter().map(|&i| i as usize))
+        .filter(|(_, i)| *i < types.len())
+        .collect();
+
+    Some(TimeZone { transitions, types, rule })
+}
+
+impl Rule {
+    /// Parse a rule as in `TZ`, such as `JST-9` or `<+03>-3`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::tzif::Rule;
+    ///
+    /// // Offsets are west of UTC, so the sign is the opposite of the offset
+    /// assert_eq!(Rule::parse("JST-9").unwrap().find(0), (32400, String::from("JST")));
+    /// assert_eq!(Rule::parse("<+03>-3").unwrap().find(0), (10800, String::from("+03")));
+    /// assert_eq!(Rule::parse("<-0330>3:30").unwrap().find(0), (-12600, String::from("-0330")));
+    ///
+    /// // Summer time is an hour ahead unless told otherwise, and follows
+    /// // the rules of the United States without dates
+    /// let rule = Rule::parse("EST5EDT").unwrap();
+    /// assert_eq!(rule.find(1782864000), (-14400, String::from("EDT")));
+    /// assert_eq!(rule.find(1796083200), (-18000, String::from("EST")));
+    ///
+    /// assert!(Rule::parse("XY").is_none());
+    /// assert!(Rule::parse("CET-1CEST,M3.5.0").is_none());
+    /// ```
+    pub fn parse(rule: &str) -> Option<Rule> {
+        let mut rest = rule;
+
+        let standard_name = name(&mut rest)?;
+        let standard = (-duration(&mut rest)?, standard_name);
+        if rest.is_empty() {
+            return Some(Rule { standard, dst: None });
+        }
+
+        let dst_name = name(&mut rest)?;
+        let dst_offset = if rest.is_empty() || rest.starts_with(',') { standard.0 + 3600 } else { -duration(&mut rest)? };
+
+        // Without dates, the rules of the United States are used
+        let (start, end) = match rest.strip_prefix(',') {
+            Some(dates) => {
+                let (start, end) = dates.split_once(',')?;
+                (RuleDate::parse(start)?, RuleDate::parse(end)?)
+            }
+            None if rest.is_empty() => (RuleDate::Month(3, 2, 0, 7200), RuleDate::Month(11, 1, 0, 7200)),
+            None => return None,
+        };
+
+        Some(Rule { standard, dst: Some(((dst_offset, dst_name), start, end)) })
+    }
+
+    /// Find the offset and abbreviation at a Unix time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ba::tzif::Rule;
+    ///
+    /// // Summer time starts at 02:00 on the last Sunday of March, 01:00 UTC
+    /// let rule = Rule::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+    /// assert_eq!(rule.find(1774746000 - 1), (3600, String::from("CET")));
+    /// assert_eq!(rule.find(1774746000), (7200, String::from("CEST")));
+    /// assert_eq!(rule.find(1796083200), (3600, String::from("CET")));
+    ///
+    /// // On the southern hemisphere summer time goes over the new year
+    /// let rule = Rule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+    /// assert_eq!(rule.find(1799971200), (39600, String::from("AEDT")));
+    /// assert_eq!(rule.find(1784073600), (36000, String::from("AEST")));
+    ///
+    /// // Julian days never count February 29, so day 60 is always March 1,
+    /// // while zero-based day 59 is February 29 in leap years
+    /// let julian = Rule::parse("EST5EDT,J60,J300").unwrap();
+    /// let zero = Rule::parse("EST5EDT,59,300").unwrap();
+    /// // 2028-02-29 and 2028-03-01 at noon UTC
+    /// assert_eq!(julian.find(1835438400).0, -18000);
+    /// assert_eq!(julian.find(1835524800).0, -14400);
+    /// assert_eq!(zero.find(1835438400).0, -14400);
+    /// // 2027-02-28 and 2027-03-01 at noon UTC
+    /// assert_eq!(zero.find(1803816000).0, -18000);
+    /// assert_eq!(zero.find(1803902400).0, -14400);
+    /// ```
+    pub fn find(&self, time: i64) -> LocalTime {
+        let (dst, start, end) = match &self.dst {
+            Some(dst) => dst,
+            None => return self.standard.clone(),
+        };
+
+        let year = match chrono::DateTime::from_timestamp(time + i64::from(self.standard.0), 0) {
+            Some(local) => local.year(),
+            None => return self.standard.clone(),
+        };
+
+        // Daylight saving time starts in standard time and ends in itself
+        let starts = start.at(year) - i64::from(self.standard.0);
+        let ends = end.at(year) - i64::from(dst.0);
+
+        let in_dst = if starts < ends {
+            starts <= time && time < ends
+        } else {
+            // On the southern hemisphere it goes over the new year
+            !(ends <= time && time < starts)
+        };
+
+        if in_dst { dst.clone() } else { self.standard.clone() }
+    }
+}
+
+impl RuleDate {
+    /// Parse a date of a rule, such as `M3.5.0` or `J60/3`.
+    fn parse(date: &str) -> Option<RuleDate> {
+        let (date, mut time) = match date.split_once('/') {
+            Some((date, time)) => (date, time),
+            None => (date, "2"),
+        };
+        let time = duration(&mut time)?;
+
+        if let Some(day) = date.strip_prefix('J') {
+            return Some(RuleDate::Julian(day.parse().ok()?, time));
+        }
+
+        if let Some(month) = date.strip_prefix('M') {
+            let mut fields = month.split('.').map(|field| field.parse().ok());
+            let (month, week, weekday) = (fields.next()??, fields.next()??, fields.next()??);
+            return Some(RuleDate::Month(month, week, weekday, time));
+        }
+
+        Some(RuleDate::Zero(date.parse().ok()?, time))
+    }
+
+    /// The local time this happens at in `year`, in seconds since the epoch.
+    fn at(&self, year: i32) -> i64 {
+        let first = NaiveDate::from_ymd_opt(year, 1, 1).unwrap_or_default();
+        let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+
+        let (date, time) = match *self {
+            RuleDate::Julian(day, time) => {
+                let day = i64::from(day.max(1)) - 1 + i64::from(leap && day >= 60);
+                (first + Duration::days(day), time)
+            }
+            RuleDate::Zero(day, time) => (first + Duration::days(i64::from(day)), time),
+            RuleDate::Month(month, week, weekday, time) => {
+                let start = first.with_month(month).unwrap_or(first);
+                let first_weekday = (weekday + 7 - start.weekday().num_days_from_sunday()) % 7;
+                let mut date = start + Duration::days(i64::from(first_weekday + 7 * (week.max(1) - 1)));
+
+                // Week 5 is the last one, which may be the fourth
+                while date.month() != start.month() {
+                    date -= Duration::weeks(1);
+                }
+                (date, time)
+            }
+        };
+
+        date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc().timestamp() + i64::from(time)
+    }
+}
+
+/// Take a name, such as `CET` or `<+03>`, from the start of a rule.
+fn name(rule: &mut &str) -> Option<String> {
+    let (name, rest) = match rule.strip_prefix('<') {
+        Some(quoted) => {
+            let (name, rest) = quoted.split_once('>')?;
+            (name, rest)
+        }
+        None => {
+            let end = rule.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rule.len());
+            rule.split_at(end)
+        }
+    };
+
+    if name.len() < 3 {
+        return None;
+    }
+
+    *rule = rest;
+    Some(String::from(name))
+}
+
+/// Take a signed `hh[:mm[:ss]]` from the start of a rule, in seconds.
+fn duration(rule: &mut &str) -> Option<i32> {
+    let sign = if rule.starts_with('-') { -1 } else { 1 };
+    let unsigned = rule.trim_start_matches(['+', '-']);
+    let end = unsigned.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(unsigned.len());
+
+    let mut seconds = 0;
+    for (i, part) in unsigned[..end].split(':').enumerate().take(3) {
+        seconds += part.parse::<i32>().ok()? * [3600, 60, 1][i];
+    }
+
+    *rule = &unsigned[end..];
+    Some(sign * seconds)
+}
@@ -0,0 +1,132 @@
+//! Just enough of the X keyboard extension to follow the keyboard layout.
+
+use std::io;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xkb::{
+    ConnectionExt as _, EventType, Group, MapPart, SelectEventsAux, SelectEventsAuxStateNotify, StatePart, ID,
+};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, ModMask, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use crate::{parse_xkb_rules_names, KeyboardState};
+
+/// A connection to the X server, listening for keyboard events.
+pub struct Keyboard {
+    connection: RustConnection,
+    root: Window,
+    layouts: Vec<String>,
+}
+
+impl Keyboard {
+    /// Connect to the X server in `$DISPLAY`.
+    pub fn connect() -> io::Result<Keyboard> {
+        let (connection, screen) = x11rb::connect(None).map_err(io::Error::other)?;
+        let root = connection.setup().roots[screen].root;
+
+        connection
+            .xkb_use_extension(1, 0)
+            .map_err(io::Error::other)?
+            .reply()
+            .map_err(io::Error::other)?;
+
+        // Layout and lock key changes, but not every press of a modifier,
+        // and new keyboards, which may come with new layouts
+        let shown = StatePart::GROUP_STATE | StatePart::GROUP_LOCK | StatePart::MODIFIER_LOCK;
+        let details = SelectEventsAux::new().state_notify(SelectEventsAuxStateNotify {
+            affect_state: shown,
+            state_details: shown,
+        });
+
+        connection
+            .xkb_select_events(
+                ID::USE_CORE_KBD.into(),
+                EventType::from(0u16),
+                EventType::NEW_KEYBOARD_NOTIFY,
+                MapPart::from(0u16),
+                MapPart::from(0u16),
+                &details,
+            )
+            .map_err(io::Error::other)?
+            .check()
+            .map_err(io::Error::other)?;
+
+        let mut keyboard = Keyboard { connection, root, layouts: Vec::new() };
+        keyboard.layouts = keyboard.read_layouts()?;
+        Ok(keyboard)
+    }
+
+    /// Read the active layout and the lock keys.
+    pub fn state(&self) -> io::Result<KeyboardState> {
+        let state = self
+            .connection
+            .xkb_get_state(ID::USE_CORE_KBD.into())
+            .map_err(io::Error::other)?
+            .reply()
+            .map_err(io::Error::other)?;
+
+        Ok(KeyboardState {
+            layouts: self.layouts.clone(),
+            active: usize::from(u8::from(state.group)),
+            caps_lock: state.locked_mods.contains(ModMask::LOCK),
+            // Num Lock is Mod2 in just about every keymap
+            num_lock: state.locked_mods.contains(ModMask::M2),
+        })
+    }
+
+    /// Wait until the keyboard state changes, reading the layouts again
+    /// if there is a new keyboard.
+    pub fn wait(&mut self) -> io::Result<()> {
+        loop {
+            match self.connection.wait_for_event().map_err(io::Error::other)? {
+                Event::XkbStateNotify(_) => return Ok(()),
+                Event::XkbNewKeyboardNotify(_) => {
+                    self.layouts = self.read_layouts()?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Switch to layout number `group`.
+    pub fn lock_group(&self, group: usize) -> io::Result<()> {
+        let none = ModMask::from(0u16);
+
+        self.connection
+            .xkb_latch_lock_state(
+                ID::USE_CORE_KBD.into(),
+                none,
+                none,
+                true,
+                Group::from(group as u8),
+                none,
+                false,
+                0,
+            )
+            .map_err(io::Error::other)?
+            .check()
+            .map_err(io::Error::other)
+    }
+
+    /// Read the short names of the layouts, such as `us`, set by `setxkbmap`.
+    fn read_layouts(&self) -> io::Result<Vec<String>> {
+        let atom = self
+            .connection
+            .intern_atom(false, b"_XKB_RULES_NAMES")
+            .map_err(io::Error::other)?
+            .reply()
+            .map_err(io::Error::other)?
+            .atom;
+
+        let names = self
+            .connection
+            .get_property(false, self.root, atom, AtomEnum::STRING, 0, 1024)
+            .map_err(io::Error::other)?
+            .reply()
+            .map_err(io::Error::other)?;
+
+        Ok(parse_xkb_rules_names(&names.value))
+    }
+}
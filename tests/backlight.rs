@@ -0,0 +1,26 @@
+mod common;
+
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+
+use ba::set_brightness_on;
+
+/// A logind session, telling what it was asked to set.
+struct Session(Mutex<Sender<(String, String, u32)>>);
+
+#[zbus::interface(name = "org.freedesktop.login1.Session")]
+impl Session {
+    fn set_brightness(&self, subsystem: String, name: String, brightness: u32) {
+        self.0.lock().unwrap().send((subsystem, name, brightness)).unwrap();
+    }
+}
+
+#[test]
+fn set_brightness() {
+    let (sender, received) = channel();
+    let session = Session(Mutex::new(sender));
+    let (client, _logind) = common::serve(|logind| logind.serve_at("/org/freedesktop/login1/session/auto", session));
+
+    set_brightness_on(&client, "intel_backlight", 750).unwrap();
+    assert_eq!(received.recv().unwrap(), ("backlight".into(), "intel_backlight".into(), 750));
+}
@@ -0,0 +1,27 @@
+//! Stub D-Bus services on a private connection, for the modules that
+//! talk to daemons over D-Bus.
+
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+
+/// Serve the objects `serve` adds to the builder of the service, and
+/// connect to it. Returns the client and the service, which serves for as
+/// long as it is kept.
+#[allow(deprecated)] // Its replacement needs the async-io crate
+pub fn serve<F>(serve: F) -> (Connection, Connection)
+where
+    F: FnOnce(Builder<'static>) -> zbus::Result<Builder<'static>> + Send + 'static,
+{
+    let (service, client) = UnixStream::pair().unwrap();
+
+    let service = thread::spawn(move || {
+        let builder = Builder::unix_stream(service).server(zbus::Guid::generate()).unwrap().p2p();
+        serve(builder).unwrap().build().unwrap()
+    });
+    let client = Builder::unix_stream(client).p2p().build().unwrap();
+
+    (client, service.join().unwrap())
+}
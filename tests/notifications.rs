@@ -0,0 +1,137 @@
+mod common;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+
+use ba::{notify_on, NotificationDaemon, NotificationStatus};
+use zbus::zvariant::{OwnedValue, Value};
+
+fn notification<T: Into<Value<'static>>>(id: T, summary: &str) -> HashMap<String, OwnedValue> {
+    let mut notification = HashMap::new();
+    notification.insert(String::from("id"), id.into().try_to_owned().unwrap());
+    notification.insert(String::from("summary"), Value::from(summary).try_to_owned().unwrap());
+    notification
+}
+
+struct Dunst {
+    paused: bool,
+}
+
+#[zbus::interface(name = "org.dunstproject.cmd0")]
+impl Dunst {
+    #[zbus(property, name = "paused")]
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    #[zbus(property, name = "paused")]
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    #[zbus(property, name = "displayedLength")]
+    fn displayed_length(&self) -> u32 {
+        2
+    }
+
+    #[zbus(property, name = "waitingLength")]
+    fn waiting_length(&self) -> u32 {
+        1
+    }
+
+    #[zbus(name = "NotificationListDisplayed")]
+    fn notification_list_displayed(&self) -> Vec<HashMap<String, OwnedValue>> {
+        vec![notification(3, "Battery low"), notification(4, "Build finished")]
+    }
+
+    #[zbus(name = "NotificationListHistory")]
+    fn notification_list_history(&self) -> Vec<HashMap<String, OwnedValue>> {
+        vec![notification(5, "Dismissed")]
+    }
+}
+
+/// Mako, which keeps the modes it is in.
+struct Mako {
+    modes: Vec<String>,
+}
+
+#[zbus::interface(name = "fr.emersion.Mako")]
+impl Mako {
+    fn list_notifications(&self) -> Vec<HashMap<String, OwnedValue>> {
+        vec![notification(8u32, "Meeting in 5 minutes"), notification(7u32, "Build finished")]
+    }
+
+    fn list_history(&self) -> Vec<HashMap<String, OwnedValue>> {
+        vec![notification(9u32, "Dismissed")]
+    }
+
+    fn list_modes(&self) -> Vec<String> {
+        self.modes.clone()
+    }
+
+    fn set_modes(&mut self, modes: Vec<String>) {
+        self.modes = modes;
+    }
+}
+
+/// A notification daemon, telling what it was sent.
+struct Daemon(Mutex<Sender<(String, String, u8)>>);
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl Daemon {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(&self, _app: String, _replaces: u32, _icon: String, summary: String,
+        body: String, _actions: Vec<String>, hints: HashMap<String, OwnedValue>,
+        _timeout: i32) -> u32
+    {
+        let urgency = u8::try_from(&hints["urgency"]).unwrap();
+        self.0.lock().unwrap().send((summary, body, urgency)).unwrap();
+        1
+    }
+}
+
+#[test]
+fn dunst() {
+    let (client, _dunst) = common::serve(|dunst| dunst.serve_at("/org/freedesktop/Notifications", Dunst { paused: false }));
+
+    let dunst = NotificationDaemon::Dunst;
+    assert_eq!(dunst.status(&client).unwrap(), NotificationStatus {
+        count: 3,
+        paused: false,
+        latest: Some(String::from("Build finished")),
+    });
+
+    dunst.set_paused(&client, true).unwrap();
+    assert!(dunst.status(&client).unwrap().paused);
+}
+
+#[test]
+fn mako() {
+    let mako = Mako { modes: vec![String::from("default")] };
+    let (client, _mako) = common::serve(|service| service.serve_at("/fr/emersion/Mako", mako));
+
+    let mako = NotificationDaemon::Mako;
+    assert_eq!(mako.status(&client).unwrap(), NotificationStatus {
+        count: 2,
+        paused: false,
+        latest: Some(String::from("Meeting in 5 minutes")),
+    });
+
+    mako.set_paused(&client, true).unwrap();
+    assert!(mako.status(&client).unwrap().paused);
+
+    mako.set_paused(&client, false).unwrap();
+    assert!(!mako.status(&client).unwrap().paused);
+}
+
+#[test]
+fn notify() {
+    let (sender, received) = channel();
+    let daemon = Daemon(Mutex::new(sender));
+    let (client, _daemon) = common::serve(|service| service.serve_at("/org/freedesktop/Notifications", daemon));
+
+    assert_eq!(notify_on(&client, "Battery low", "10% left", true).unwrap(), 1);
+    assert_eq!(received.recv().unwrap(), ("Battery low".into(), "10% left".into(), 2));
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ba::{unit_problems, watch_units, UnitStatus};
+use zbus::zvariant::{OwnedObjectPath, Value};
+
+fn path(path: &str) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(path).unwrap()
+}
+
+/// The manager, telling when it is subscribed to.
+struct Manager(Mutex<Sender<()>>);
+
+#[zbus::interface(name = "org.freedesktop.systemd1.Manager")]
+impl Manager {
+    #[zbus(name = "ListUnitsFiltered")]
+    fn list_units_filtered(&self, states: Vec<String>) -> Vec<UnitStatus> {
+        assert_eq!(states, ["failed"]);
+        vec![(
+            "backup.service".into(), "Backup".into(), "loaded".into(), "failed".into(),
+            "failed".into(), "".into(), path("/unit/backup"), 0, "".into(), path("/"),
+        )]
+    }
+
+    #[zbus(name = "LoadUnit")]
+    fn load_unit(&self, name: String) -> OwnedObjectPath {
+        path(&format!("/unit/{}", name.trim_end_matches(".service")))
+    }
+
+    #[zbus(name = "Subscribe")]
+    fn subscribe(&self) {
+        self.0.lock().unwrap().send(()).unwrap();
+    }
+}
+
+struct Unit(&'static str);
+
+#[zbus::interface(name = "org.freedesktop.systemd1.Unit")]
+impl Unit {
+    #[zbus(property, name = "ActiveState")]
+    fn active_state(&self) -> String {
+        String::from(self.0)
+    }
+}
+
+#[test]
+fn problems() {
+    let (subscribed, _) = channel();
+    let (client, _systemd) = common::serve(|systemd| {
+        systemd
+            .serve_at("/org/freedesktop/systemd1", Manager(Mutex::new(subscribed)))?
+            .serve_at("/unit/backup", Unit("failed"))?
+            .serve_at("/unit/sshd", Unit("active"))?
+            .serve_at("/unit/nginx", Unit("inactive"))
+    });
+
+    let units = ["sshd.service", "nginx.service", "backup.service"];
+    assert_eq!(unit_problems(&client, &units).unwrap(), ["backup.service", "nginx.service"]);
+}
+
+#[test]
+fn watch() {
+    let (subscribed, subscriptions) = channel();
+    let (client, systemd) = common::serve(|systemd| {
+        systemd.serve_at("/org/freedesktop/systemd1", Manager(Mutex::new(subscribed)))
+    });
+
+    let (sender, changes) = channel();
+    let sender = Mutex::new(sender);
+    let watcher = watch_units(client.clone(), Box::new(move || sender.lock().unwrap().send(()).unwrap())).unwrap();
+    subscriptions.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    let changed = |interface: &str, property: &str| {
+        let properties = HashMap::from([(property, Value::from("failed"))]);
+        let invalidated: Vec<&str> = Vec::new();
+        systemd
+            .emit_signal(
+                None::<()>,
+                "/unit/backup",
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &(interface, properties, invalidated),
+            )
+            .unwrap();
+    };
+
+    // Only the active state of units matters
+    changed("org.freedesktop.systemd1.Service", "ActiveState");
+    changed("org.freedesktop.systemd1.Unit", "SubState");
+    changed("org.freedesktop.systemd1.Unit", "ActiveState");
+    changes.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(changes.recv_timeout(Duration::from_millis(200)).is_err());
+
+    // Until the connection is lost
+    client.close().unwrap();
+    watcher.join().unwrap();
+}